@@ -47,3 +47,165 @@ grep -cri the tests/inputs > "$OUT_DIR/the.recursive.insensitive.count"
 
 # STDIN, insensitive, count
 cat tests/inputs/*.txt | grep -ci the - > "$OUT_DIR/the.recursive.insensitive.count.stdin"
+
+# Line numbers
+grep -n The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.n"
+grep -n The $DIR/*.txt > "$OUT_DIR/all.the.capitalized.n"
+grep -nv nobody tests/inputs/nobody.txt > "$OUT_DIR/nobody.txt.invert.n"
+
+# After context
+grep -A 1 The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.A1"
+grep -A 3 The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.A3"
+
+# Before context
+grep -B 1 The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.B1"
+grep -B 3 The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.B3"
+grep -B 2 -A 1 public tests/inputs/nobody.txt > "$OUT_DIR/nobody.txt.public.B2.A1"
+
+# Combined context
+grep -C 1 public tests/inputs/nobody.txt > "$OUT_DIR/nobody.txt.public.C1"
+grep -C 1 -B 2 public tests/inputs/nobody.txt > "$OUT_DIR/nobody.txt.public.C1.B2"
+
+# Context lines separate fields with - where matches use :
+grep -Hnb -C 1 industries tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.industries.Hnb.C1"
+
+# Only matching
+grep -o the tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.lowercase.o"
+grep -on "[Tt]he" $DIR/*.txt > "$OUT_DIR/all.the.o.n"
+
+# Files with matches
+grep -l The $DIR/*.txt > "$OUT_DIR/all.the.capitalized.l"
+grep -lv nobody $DIR/*.txt > "$OUT_DIR/all.nobody.lv"
+
+# Files without match
+grep -L The $DIR/*.txt > "$OUT_DIR/all.the.capitalized.L"
+
+# Max count
+grep -m 2 The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.m2"
+grep -c -m 2 The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.m2.count"
+grep -cv -m 3 The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.m3.invert.count"
+
+# Word regexp
+grep -w the tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.lowercase.w"
+grep -Ewi "the|us" tests/inputs/nobody.txt > "$OUT_DIR/nobody.txt.the.us.wi"
+
+# Multiple patterns
+grep -e fox -e Nobody $DIR/*.txt > "$OUT_DIR/all.fox.nobody.e"
+grep -c -e The -e the tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.e.count"
+
+# Include and exclude globs
+grep -ri --include="*.txt" --exclude="b*" --exclude="n*" the tests/inputs > "$OUT_DIR/the.recursive.include.exclude"
+
+# Exclude directories (walk order varies, so sort)
+grep -r --exclude-dir=target --exclude-dir="v*" needle tests/tree | sort > "$OUT_DIR/needle.tree.exclude-dir"
+
+# Include directories (GNU grep has no --include-dir; find emulates it, sorted)
+find tests/tree -mindepth 1 -type d ! -name src -prune -o -type f -exec grep -H needle {} + | sort > "$OUT_DIR/needle.tree.include-dir"
+find tests/tree -mindepth 1 -type d ! -name src ! -name nested -prune -o -type f -exec grep -H needle {} + | sort > "$OUT_DIR/needle.tree.include-dir.nested"
+
+# Filename prefix modes
+grep -h The $DIR/*.txt > "$OUT_DIR/all.the.capitalized.h"
+grep -H The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.H"
+
+# Inverted, multiple files
+grep -v The $DIR/*.txt > "$OUT_DIR/all.the.capitalized.invert"
+
+# NUL after file names
+grep -lZ The $DIR/*.txt > "$OUT_DIR/all.the.capitalized.lZ"
+grep -LZ The $DIR/*.txt > "$OUT_DIR/all.the.capitalized.LZ"
+grep -Z The $DIR/*.txt > "$OUT_DIR/all.the.capitalized.Z"
+
+# Byte offsets
+grep -b The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.b"
+grep -nb -e To -e How tests/inputs/nobody.txt > "$OUT_DIR/nobody.txt.to.how.nb"
+grep -ob "Nobody" tests/inputs/nobody.txt > "$OUT_DIR/nobody.txt.ob"
+
+# Mixed line endings (grep keeps the \r for matching, so these are written by hand)
+# tests/expected/mixed.txt.apple.x, mixed.txt.grape.x, mixed.txt.e.anchor{,.strip-cr}
+
+# Max depth (GNU grep has no --max-depth; find emulates it, sorted like the test)
+find tests/tree -maxdepth 1 -type f -exec grep -H needle {} + > "$OUT_DIR/needle.tree.max-depth1"
+find tests/tree -maxdepth 2 -name target -prune -o -type f -exec grep -H needle {} + | sort > "$OUT_DIR/needle.tree.max-depth2.exclude-dir"
+
+# Following symlinks
+grep -R needle tests/links | sort > "$OUT_DIR/needle.links.follow"
+
+# JSON Lines output (grep has no equivalent, generated with grepr and checked by hand)
+cargo run -q -- --json fox tests/inputs/fox.txt > "$OUT_DIR/fox.txt.json"
+cargo run -q -- --json -e Nobody -e " are " $DIR/*.txt > "$OUT_DIR/all.nobody.are.json"
+cargo run -q -- --json -v The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.v.json"
+
+# Count every match
+for f in $DIR/*.txt; do echo "$f:$(grep -oi the $f | wc -l)"; done > "$OUT_DIR/all.the.insensitive.count-matches"
+
+# Unterminated last line
+grep -B1 last tests/endings/no-eol.txt > "$OUT_DIR/no-eol.txt.last.B1"
+grep -v first tests/endings/no-eol.txt > "$OUT_DIR/no-eol.txt.first.v"
+
+# Group separators
+grep -A 1 --group-separator=## The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.A1.group-separator"
+grep -A 1 --no-group-separator The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.A1.no-group-separator"
+
+# Multiline (grep can't match across lines, generated with grepr and checked by hand)
+cargo run -q -- -U 'house\r\nThe' tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.multiline"
+cargo run -q -- -U -n -A1 'house\r\nThe' tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.multiline.n.A1"
+
+# Perl regexes
+grep -P 'fox(?= jumps)' tests/inputs/fox.txt > "$OUT_DIR/fox.txt.P.lookahead"
+grep -Pn '(\w)\1' tests/inputs/bustle.txt tests/inputs/nobody.txt > "$OUT_DIR/bustle.nobody.P.backref.n"
+
+# Headings (grep has none, generated with grepr and checked by hand)
+cargo run -q -- --heading -n The $DIR/*.txt > "$OUT_DIR/all.the.capitalized.heading.n"
+
+# NUL-terminated records
+grep -z pie tests/endings/null-data.txt > "$OUT_DIR/null-data.txt.pie.z"
+grep -zc pie tests/endings/null-data.txt > "$OUT_DIR/null-data.txt.pie.zc"
+grep -zob pie tests/endings/null-data.txt > "$OUT_DIR/null-data.txt.pie.zob"
+grep -zvn pie tests/endings/null-data.txt > "$OUT_DIR/null-data.txt.pie.zvn"
+grep -z 'split$' tests/endings/null-data.txt > "$OUT_DIR/null-data.txt.split.z"
+
+# Sorted recursion
+grep -r needle tests/tree | sort > "$OUT_DIR/needle.tree.sorted"
+
+# Other encodings (grep reads bytes, so iconv transcodes first)
+iconv -f latin1 -t utf-8 tests/encodings/latin1.txt | grep -n é > "$OUT_DIR/latin1.txt.e-acute.n"
+iconv -f utf-16le -t utf-8 tests/encodings/utf16le.txt | grep café > "$OUT_DIR/utf16.txt.cafe"
+
+# Replacements (grep has no --replace, so sed does the substituting)
+grep -n The tests/inputs/bustle.txt | sed 's/The/A/g' > "$OUT_DIR/bustle.txt.the.capitalized.n.replace"
+grep -o "[Tt]he" $DIR/*.txt | sed 's/:.*/:X/' > "$OUT_DIR/all.the.o.replace"
+
+# Match columns (GNU grep has no --column, generated with grepr and checked by hand)
+cargo run -q -- -n --column -i the $DIR/*.txt > "$OUT_DIR/all.the.lowercase.insensitive.n.column"
+cargo run -q -- -o --column -i the $DIR/*.txt > "$OUT_DIR/all.the.lowercase.insensitive.o.column"
+
+# Zero-width patterns
+grep -n "^" tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.anchor.n"
+
+# Long line truncation (grep has no --max-columns, generated with grepr and checked by hand)
+cargo run -q -- --max-columns=10 -n The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.n.max-columns10"
+
+# Inverted counts
+grep -cv first tests/endings/no-eol.txt > "$OUT_DIR/no-eol.txt.first.cv"
+grep -cv last tests/endings/no-eol.txt > "$OUT_DIR/no-eol.txt.last.cv"
+grep -cv zzz tests/endings/no-eol.txt > "$OUT_DIR/no-eol.txt.zzz.cv"
+grep -cv The tests/inputs/empty.txt > "$OUT_DIR/empty.txt.the.cv"
+grep -cv The tests/inputs/bustle.txt tests/inputs/empty.txt tests/endings/no-eol.txt tests/inputs/fox.txt > "$OUT_DIR/mixed.the.capitalized.cv"
+grep -cvh The tests/inputs/bustle.txt tests/inputs/empty.txt > "$OUT_DIR/bustle.empty.the.capitalized.cvh"
+grep -cvH The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.cvH"
+grep -cv The < tests/endings/no-eol.txt > "$OUT_DIR/no-eol.txt.the.cv.stdin"
+
+# Context across files (grep also prints -- between files, grepr only within one;
+# generated with grepr and checked by hand against grep with those lines removed)
+cargo run -q -- -A1 The tests/inputs/bustle.txt tests/inputs/fox.txt tests/inputs/nobody.txt > "$OUT_DIR/all.the.capitalized.A1"
+cargo run -q -- -C1 -e Is -e quick -e Nobody tests/inputs/bustle.txt tests/inputs/fox.txt tests/inputs/nobody.txt > "$OUT_DIR/all.is.quick.nobody.C1"
+cargo run -q -- -h -A1 The tests/inputs/bustle.txt tests/inputs/empty.txt tests/inputs/fox.txt > "$OUT_DIR/all.the.capitalized.A1.h"
+
+# Counts with each file name mode
+grep -cH The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.count.H"
+grep -ch The tests/inputs/bustle.txt > "$OUT_DIR/bustle.txt.the.capitalized.count.h"
+grep -cH The tests/inputs/empty.txt > "$OUT_DIR/empty.txt.the.capitalized.count.H"
+grep -ch The $DIR/*.txt > "$OUT_DIR/all.the.capitalized.count.h"
+
+# Counts sorted from most to fewest, ties by name
+grep -ci the tests/inputs/nobody.txt tests/inputs/fox.txt tests/inputs/empty.txt tests/inputs/bustle.txt | sort -t: -k2,2nr -k1,1 > "$OUT_DIR/all.the.lowercase.insensitive.sort-count"
@@ -1,166 +1,4092 @@
-use clap::{App, Arg};
-use regex::{Regex, RegexBuilder};
+use clap::{App, Arg, ErrorKind};
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use flate2::read::GzDecoder;
+use glob::Pattern;
+use memmap2::Mmap;
+use regex::bytes::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
 use std::error::Error;
+use std::fmt;
 use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, IsTerminal, Read, Write};
+use std::iter;
+use std::ops::Range;
+use std::path::{Path, MAIN_SEPARATOR};
+use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
+use std::str;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 use text_colorizer::*;
-use walkdir::WalkDir;
+use walkdir::{DirEntry, WalkDir};
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
+/// Why a file couldn't be opened or read. Failures on files box one of these, so a
+/// library caller can downcast the error to match on the cause.
+#[derive(Debug)]
+pub enum GreprError {
+    NotFound {
+        path: String,
+        source: io::Error,
+    },
+    PermissionDenied {
+        path: String,
+        source: io::Error,
+    },
+    /// A directory named as a file, without `-r`
+    IsDirectory {
+        path: String,
+    },
+    /// A file that must be text, like a pattern file, isn't UTF-8
+    InvalidUtf8 {
+        path: String,
+        source: io::Error,
+    },
+    /// Any other failure, such as a device error or a --pre command that failed
+    Other {
+        path: String,
+        source: Box<dyn Error + Send + Sync>,
+    },
+}
+
+impl GreprError {
+    // Sorts an OS error on `path` by its cause
+    fn io(path: &str, source: io::Error) -> Self {
+        let path = path.to_string();
+        match source.kind() {
+            io::ErrorKind::NotFound => GreprError::NotFound { path, source },
+            io::ErrorKind::PermissionDenied => GreprError::PermissionDenied { path, source },
+            io::ErrorKind::IsADirectory => GreprError::IsDirectory { path },
+            _ => GreprError::Other {
+                path,
+                source: Box::new(source),
+            },
+        }
+    }
+
+    // Like `io`, for errors from reading a whole file as a `String`, where invalid
+    // data can only mean the bytes weren't UTF-8
+    fn text(path: &str, source: io::Error) -> Self {
+        match source.kind() {
+            io::ErrorKind::InvalidData => GreprError::InvalidUtf8 {
+                path: path.to_string(),
+                source,
+            },
+            _ => GreprError::io(path, source),
+        }
+    }
+
+    // Any error on `path`, still sorting the I/O ones
+    fn other(path: &str, e: Box<dyn Error>) -> Self {
+        match e.downcast::<io::Error>() {
+            Ok(e) => GreprError::io(path, *e),
+            Err(e) => GreprError::Other {
+                path: path.to_string(),
+                source: e.to_string().into(),
+            },
+        }
+    }
+
+    /// The path the error happened on
+    pub fn path(&self) -> &str {
+        match self {
+            GreprError::NotFound { path, .. }
+            | GreprError::PermissionDenied { path, .. }
+            | GreprError::IsDirectory { path }
+            | GreprError::InvalidUtf8 { path, .. }
+            | GreprError::Other { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for GreprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GreprError::NotFound { path, source }
+            | GreprError::PermissionDenied { path, source }
+            | GreprError::InvalidUtf8 { path, source } => write!(f, "{}: {}", path, source),
+            GreprError::IsDirectory { path } => {
+                write!(f, "{}: Is a directory (use -r to search recursively)", path)
+            }
+            GreprError::Other { path, source } => write!(f, "{}: {}", path, source),
+        }
+    }
+}
+
+impl Error for GreprError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GreprError::NotFound { source, .. }
+            | GreprError::PermissionDenied { source, .. }
+            | GreprError::InvalidUtf8 { source, .. } => Some(source),
+            GreprError::IsDirectory { .. } => None,
+            GreprError::Other { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+/// When to prefix output lines with the file name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WithFilename {
+    /// Only when more than one file is searched
+    Auto,
+    Always,
+    Never,
+}
+
+/// How to treat files that look binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryFiles {
+    /// Report "Binary file X matches" instead of printing lines
+    Binary,
+    /// Search and print as if the file were text
+    Text,
+    /// Assume binary files never match
+    WithoutMatch,
+}
+
+/// The order in which to search files; by default they are searched as found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    Path,
+    /// Oldest first
+    Modified,
+}
+
+/// What to do with a directory named on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Directories {
+    /// Report it as an error, since a directory can't be searched as a file
+    Read,
+    /// Leave it out without a word
+    Skip,
+    /// Search every file under it, as `-r` does
+    Recurse,
+}
+
+/// What to do with devices, FIFOs and sockets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Devices {
+    Read,
+    Skip,
+}
+
+/// Whether to read files through a memory map instead of buffered reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmapMode {
+    /// Only for large regular files
+    Auto,
+    Always,
+    Never,
+}
+
+/// Foreground and background color of one kind of output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+}
+
+impl Style {
+    fn paint(&self, text: &str) -> String {
+        let mut painted = text.normal();
+        if let Some(fg) = self.fg {
+            painted = painted.color(fg);
+        }
+        if let Some(bg) = self.bg {
+            painted = painted.on_color(bg);
+        }
+        painted.to_string()
+    }
+}
+
+/// Output colors, changed on the command line with `--colors`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Colors {
+    pub matched: Style,
+    pub path: Style,
+    pub line: Style,
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            matched: Style {
+                fg: Some(Color::Green),
+                bg: None,
+            },
+            path: Style::default(),
+            line: Style::default(),
+        }
+    }
+}
+
+/// A compiled pattern, from the `regex` crate or from `fancy-regex` for -P
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    Regex(Regex),
+    Fancy(fancy_regex::Regex),
+}
+
+impl Matcher {
+    fn is_match(&self, text: &[u8]) -> bool {
+        match self {
+            Matcher::Regex(re) => re.is_match(text),
+            // fancy-regex only searches UTF-8, so other text never matches
+            Matcher::Fancy(re) => {
+                str::from_utf8(text).is_ok_and(|text| re.is_match(text).unwrap_or(false))
+            }
+        }
+    }
+
+    // Byte ranges of every match in `text`, including zero-width ones
+    fn find_iter(&self, text: &[u8]) -> Vec<Range<usize>> {
+        match self {
+            Matcher::Regex(re) => re.find_iter(text).map(|m| m.range()).collect(),
+            Matcher::Fancy(re) => match str::from_utf8(text) {
+                Ok(text) => re
+                    .find_iter(text)
+                    .map_while(Result::ok)
+                    .map(|m| m.range())
+                    .collect(),
+                Err(_) => vec![],
+            },
+        }
+    }
+
+    // `text` with every match replaced by `rep`, expanding `$1`-style references
+    fn replace_all(&self, text: &[u8], rep: &str) -> Vec<u8> {
+        match self {
+            Matcher::Regex(re) => re.replace_all(text, rep.as_bytes()).into_owned(),
+            Matcher::Fancy(re) => match str::from_utf8(text) {
+                Ok(text) => re.replace_all(text, rep).into_owned().into_bytes(),
+                Err(_) => text.to_vec(),
+            },
+        }
+    }
+
+    // The range of each non-empty match with `rep` expanded for it, for -o
+    fn replacements(&self, text: &[u8], rep: &str) -> Vec<(Range<usize>, Vec<u8>)> {
+        match self {
+            Matcher::Regex(re) => re
+                .captures_iter(text)
+                .filter_map(|caps| {
+                    let range = caps.get(0)?.range();
+                    let mut dst = vec![];
+                    caps.expand(rep.as_bytes(), &mut dst);
+                    (!range.is_empty()).then_some((range, dst))
+                })
+                .collect(),
+            Matcher::Fancy(re) => match str::from_utf8(text) {
+                Ok(text) => re
+                    .captures_iter(text)
+                    .map_while(Result::ok)
+                    .filter_map(|caps| {
+                        let range = caps.get(0)?.range();
+                        let mut dst = String::new();
+                        caps.expand(rep, &mut dst);
+                        (!range.is_empty()).then_some((range, dst.into_bytes()))
+                    })
+                    .collect(),
+                Err(_) => vec![],
+            },
+        }
+    }
+}
+
+impl From<Regex> for Matcher {
+    fn from(re: Regex) -> Matcher {
+        Matcher::Regex(re)
+    }
+}
+
 #[derive(Debug)]
 pub struct Config {
-    pattern: Regex,
+    pattern: Matcher,
+    // --and patterns a selected line must also match, and --not ones it mustn't
+    and_patterns: Vec<Matcher>,
+    not_patterns: Vec<Matcher>,
     files: Vec<String>,
-    recursive: bool,
+    directories: Directories,
     count: bool,
     invert_match: bool,
+    line_number: bool,
+    after_context: usize,
+    before_context: usize,
+    only_matching: bool,
+    // With -o, print just the first match of each line
+    first_match: bool,
+    color: bool,
+    files_with_matches: bool,
+    files_without_match: bool,
+    max_count: Option<usize>,
+    // Matching lines to print across all files before the search stops
+    max_matches_total: Option<usize>,
+    word_regexp: bool,
+    line_regexp: bool,
+    fixed_strings: bool,
+    // Read patterns as POSIX basic regexes, rewritten when they're compiled
+    basic_regexp: bool,
+    include: Vec<Pattern>,
+    // --glob patterns in order, each true to include or false to exclude
+    globs: Vec<(Pattern, bool)>,
+    exclude: Vec<Pattern>,
+    exclude_dir: Vec<Pattern>,
+    include_dir: Vec<Pattern>,
+    with_filename: WithFilename,
+    null: bool,
+    // Every prefix field and each printed line end with a NUL: `file\0line\0text\0`
+    null_fields: bool,
+    // Between the fields of matching lines and counts, `:` unless changed
+    field_separator: String,
+    quiet: bool,
+    count_total: bool,
+    byte_offset: bool,
+    binary_files: BinaryFiles,
+    // Match raw bytes over the whole input and print each match's offset and hex
+    byte_search: bool,
+    threads: usize,
+    timeout: Option<Duration>,
+    // When `run` gives up, from `timeout`; line reading stops once it passes
+    deadline: Option<Instant>,
+    mmap: MmapMode,
+    strip_cr: bool,
+    max_depth: Option<usize>,
+    follow: bool,
+    json: bool,
+    count_matches: bool,
+    stats: bool,
+    final_newline: bool,
+    group_separator: Option<String>,
+    multiline: bool,
+    dotall: bool,
+    no_messages: bool,
+    colors: Colors,
+    max_filesize: Option<u64>,
+    smart_case: bool,
+    perl_regexp: bool,
+    // Caps on the compiled program and the lazy DFA's cache, in bytes
+    regex_size_limit: Option<usize>,
+    dfa_size_limit: Option<usize>,
+    no_zero: bool,
+    // Hold counts back and print them most first, ties by name
+    sort_count: bool,
+    line_buffered: bool,
+    heading: bool,
+    null_data: bool,
+    sort: Option<SortBy>,
+    encoding: Option<&'static Encoding>,
+    replace: Option<String>,
+    in_place: bool,
+    backup: Option<String>,
+    column: bool,
+    file_match: bool,
+    progress: bool,
+    pre: Option<String>,
+    search_zip: bool,
+    // Search the members of .tar files, named `archive.tar:member`
+    search_archive: bool,
+    max_columns: Option<usize>,
+    trim: bool,
+    label: Option<String>,
+    // Printed in file names in place of the platform's own separator
+    path_separator: Option<char>,
+    // `None` reads those named on the command line but skips those found recursing
+    devices: Option<Devices>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            pattern: Regex::new("").unwrap().into(),
+            and_patterns: vec![],
+            not_patterns: vec![],
+            files: vec![],
+            directories: Directories::Read,
+            count: false,
+            invert_match: false,
+            line_number: false,
+            after_context: 0,
+            before_context: 0,
+            only_matching: false,
+            first_match: false,
+            color: false,
+            files_with_matches: false,
+            files_without_match: false,
+            max_count: None,
+            max_matches_total: None,
+            word_regexp: false,
+            line_regexp: false,
+            fixed_strings: false,
+            basic_regexp: false,
+            include: vec![],
+            globs: vec![],
+            exclude: vec![],
+            exclude_dir: vec![],
+            include_dir: vec![],
+            with_filename: WithFilename::Auto,
+            null: false,
+            null_fields: false,
+            field_separator: ":".to_string(),
+            quiet: false,
+            count_total: false,
+            byte_offset: false,
+            binary_files: BinaryFiles::Binary,
+            byte_search: false,
+            threads: 1,
+            timeout: None,
+            deadline: None,
+            mmap: MmapMode::Auto,
+            strip_cr: false,
+            max_depth: None,
+            follow: false,
+            json: false,
+            count_matches: false,
+            stats: false,
+            final_newline: true,
+            group_separator: Some("--".to_string()),
+            multiline: false,
+            dotall: false,
+            no_messages: false,
+            colors: Colors::default(),
+            max_filesize: None,
+            smart_case: false,
+            perl_regexp: false,
+            regex_size_limit: None,
+            dfa_size_limit: None,
+            no_zero: false,
+            sort_count: false,
+            line_buffered: false,
+            heading: false,
+            null_data: false,
+            sort: None,
+            encoding: None,
+            replace: None,
+            in_place: false,
+            backup: None,
+            column: false,
+            file_match: false,
+            progress: false,
+            pre: None,
+            search_zip: false,
+            search_archive: false,
+            max_columns: None,
+            trim: false,
+            label: None,
+            path_separator: None,
+            devices: None,
+        }
+    }
+}
+
+impl Config {
+    // Whether a line matches the pattern and every --and pattern, but no --not one
+    fn selects(&self, text: &[u8]) -> bool {
+        self.pattern.is_match(text)
+            && self.and_patterns.iter().all(|p| p.is_match(text))
+            && !self.not_patterns.iter().any(|p| p.is_match(text))
+    }
+
+    // Byte ranges of the pattern's non-empty matches in `line`, ignoring its terminator
+    fn line_spans(&self, line: &[u8]) -> Vec<Range<usize>> {
+        match_spans(&self.pattern, trim_newline(line, self.null_data))
+    }
+}
+
+/// Builds a [`Config`] for library use, compiling the patterns in [`build`](Self::build)
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    patterns: Vec<String>,
+    and_patterns: Vec<String>,
+    not_patterns: Vec<String>,
+    insensitive: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    exclude_dir: Vec<String>,
+    include_dir: Vec<String>,
+    globs: Vec<String>,
+    config: Config,
 }
 
-impl Default for Config {
-    fn default() -> Config {
-        Config {
-            pattern: Regex::new("").unwrap(),
-            files: vec![],
-            recursive: false,
-            count: false,
-            invert_match: false,
+impl ConfigBuilder {
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Adds a pattern; lines matching any of the patterns are selected
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Adds a pattern that selected lines must also match
+    pub fn and_pattern(mut self, pattern: &str) -> Self {
+        self.and_patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Adds a pattern that selected lines must not match
+    pub fn not_pattern(mut self, pattern: &str) -> Self {
+        self.not_patterns.push(pattern.to_string());
+        self
+    }
+
+    pub fn files(mut self, files: &[&str]) -> Self {
+        self.config.files = files.iter().map(|f| f.to_string()).collect();
+        self
+    }
+
+    /// Match case-insensitively; inline `(?i)`/`(?-i)` flags in a pattern still win
+    pub fn insensitive(mut self, insensitive: bool) -> Self {
+        self.insensitive = insensitive;
+        self
+    }
+
+    /// Match case-insensitively unless a pattern contains an uppercase letter
+    pub fn smart_case(mut self, smart_case: bool) -> Self {
+        self.config.smart_case = smart_case;
+        self
+    }
+
+    /// Compile patterns with `fancy-regex`, for lookaround and backreferences
+    pub fn perl_regexp(mut self, perl_regexp: bool) -> Self {
+        self.config.perl_regexp = perl_regexp;
+        self
+    }
+
+    /// Refuse patterns whose compiled form is larger than this many bytes
+    pub fn regex_size_limit(mut self, limit: Option<usize>) -> Self {
+        self.config.regex_size_limit = limit;
+        self
+    }
+
+    /// Let the lazy DFA cache use up to this many bytes before falling back to a
+    /// slower engine. Unlike the regex limit, going over it is never an error.
+    pub fn dfa_size_limit(mut self, limit: Option<usize>) -> Self {
+        self.config.dfa_size_limit = limit;
+        self
+    }
+
+    pub fn fixed_strings(mut self, fixed_strings: bool) -> Self {
+        self.config.fixed_strings = fixed_strings;
+        self
+    }
+
+    /// Read patterns as POSIX basic regexes, where `\+` and `\(` are the operators.
+    /// Backreferences like `\1` aren't supported.
+    pub fn basic_regexp(mut self, basic_regexp: bool) -> Self {
+        self.config.basic_regexp = basic_regexp;
+        self
+    }
+
+    pub fn word_regexp(mut self, word_regexp: bool) -> Self {
+        self.config.word_regexp = word_regexp;
+        self
+    }
+
+    pub fn line_regexp(mut self, line_regexp: bool) -> Self {
+        self.config.line_regexp = line_regexp;
+        self
+    }
+
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.config.directories = match recursive {
+            true => Directories::Recurse,
+            false => Directories::Read,
+        };
+        self
+    }
+
+    /// Read, skip or recurse into directories named as files; `recursive` is a shorthand
+    pub fn directories(mut self, directories: Directories) -> Self {
+        self.config.directories = directories;
+        self
+    }
+
+    pub fn count(mut self, count: bool) -> Self {
+        self.config.count = count;
+        self
+    }
+
+    pub fn invert_match(mut self, invert_match: bool) -> Self {
+        self.config.invert_match = invert_match;
+        self
+    }
+
+    pub fn line_number(mut self, line_number: bool) -> Self {
+        self.config.line_number = line_number;
+        self
+    }
+
+    pub fn after_context(mut self, num: usize) -> Self {
+        self.config.after_context = num;
+        self
+    }
+
+    pub fn before_context(mut self, num: usize) -> Self {
+        self.config.before_context = num;
+        self
+    }
+
+    pub fn context(self, num: usize) -> Self {
+        self.before_context(num).after_context(num)
+    }
+
+    /// Line printed between context groups, `None` to print nothing
+    pub fn group_separator(mut self, group_separator: Option<&str>) -> Self {
+        self.config.group_separator = group_separator.map(str::to_string);
+        self
+    }
+
+    /// Split the input into NUL-terminated records instead of lines
+    pub fn null_data(mut self, null_data: bool) -> Self {
+        self.config.null_data = null_data;
+        self
+    }
+
+    /// Match against the whole input so patterns can span lines.
+    ///
+    /// Every line touched by a match is selected, and `only_matching` prints whole matches.
+    pub fn multiline(mut self, multiline: bool) -> Self {
+        self.config.multiline = multiline;
+        self
+    }
+
+    /// Let `.` match newlines; only has an effect together with `multiline`
+    pub fn dotall(mut self, dotall: bool) -> Self {
+        self.config.dotall = dotall;
+        self
+    }
+
+    pub fn only_matching(mut self, only_matching: bool) -> Self {
+        self.config.only_matching = only_matching;
+        self
+    }
+
+    /// Print only the first match on each line with -o; by default, as in grep, every
+    /// match is printed.
+    pub fn first_match(mut self, first_match: bool) -> Self {
+        self.config.first_match = first_match;
+        self
+    }
+
+    pub fn color(mut self, color: bool) -> Self {
+        self.config.color = color;
+        self
+    }
+
+    pub fn colors(mut self, colors: Colors) -> Self {
+        self.config.colors = colors;
+        self
+    }
+
+    pub fn files_with_matches(mut self, files_with_matches: bool) -> Self {
+        self.config.files_with_matches = files_with_matches;
+        self
+    }
+
+    /// Match each file as a single blob instead of line by line; files are read into
+    /// memory whole, so this needs as much memory as the largest file searched
+    pub fn file_match(mut self, file_match: bool) -> Self {
+        self.config.file_match = file_match;
+        self
+    }
+
+    /// Cut printed lines and -o matches to this many bytes
+    pub fn max_columns(mut self, max_columns: Option<usize>) -> Self {
+        self.config.max_columns = max_columns;
+        self
+    }
+
+    /// Drop the leading whitespace of printed lines; columns and offsets still count it
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.config.trim = trim;
+        self
+    }
+
+    pub fn files_without_match(mut self, files_without_match: bool) -> Self {
+        self.config.files_without_match = files_without_match;
+        self
+    }
+
+    pub fn max_count(mut self, max_count: Option<usize>) -> Self {
+        self.config.max_count = max_count;
+        self
+    }
+
+    /// Stop the whole search, not just a file, after this many matching lines
+    pub fn max_matches_total(mut self, max_matches_total: Option<usize>) -> Self {
+        self.config.max_matches_total = max_matches_total;
+        self
+    }
+
+    /// Only search files whose name matches one of these globs when recursing
+    pub fn include(mut self, glob: &str) -> Self {
+        self.include.push(glob.to_string());
+        self
+    }
+
+    /// Skip files whose name matches any of these globs when recursing
+    pub fn exclude(mut self, glob: &str) -> Self {
+        self.exclude.push(glob.to_string());
+        self
+    }
+
+    /// Include files matching this glob when recursing, or exclude them if it starts
+    /// with `!`. When several match a file the last one added wins; if only
+    /// excluding globs were added, files matching none are still searched.
+    pub fn glob(mut self, glob: &str) -> Self {
+        self.globs.push(glob.to_string());
+        self
+    }
+
+    /// Skip directories whose name matches this glob when recursing
+    pub fn exclude_dir(mut self, glob: &str) -> Self {
+        self.exclude_dir.push(glob.to_string());
+        self
+    }
+
+    /// Only descend into directories whose name matches this glob when recursing.
+    /// A directory matching an [`exclude_dir`](Self::exclude_dir) glob is still skipped.
+    pub fn include_dir(mut self, glob: &str) -> Self {
+        self.include_dir.push(glob.to_string());
+        self
+    }
+
+    /// Read or skip devices, FIFOs and sockets, wherever they were found
+    pub fn devices(mut self, devices: Devices) -> Self {
+        self.config.devices = Some(devices);
+        self
+    }
+
+    /// Skip files larger than `max_filesize` bytes
+    pub fn max_filesize(mut self, max_filesize: Option<u64>) -> Self {
+        self.config.max_filesize = max_filesize;
+        self
+    }
+
+    pub fn sort(mut self, sort: Option<SortBy>) -> Self {
+        self.config.sort = sort;
+        self
+    }
+
+    /// Limit recursion to `max_depth` levels below each directory argument
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.config.max_depth = max_depth;
+        self
+    }
+
+    /// Follow symbolic links when recursing
+    pub fn follow(mut self, follow: bool) -> Self {
+        self.config.follow = follow;
+        self
+    }
+
+    pub fn with_filename(mut self, with_filename: WithFilename) -> Self {
+        self.config.with_filename = with_filename;
+        self
+    }
+
+    /// Terminate file names with a NUL byte instead of `:` or a newline
+    pub fn null(mut self, null: bool) -> Self {
+        self.config.null = null;
+        self
+    }
+
+    /// End every output field with a NUL byte, so `file\0line\0text\0` parses
+    /// unambiguously even when names or text hold `:` or newlines.
+    /// Lines are printed without their own terminator, and file names alone
+    /// (as with `-l`) end in a NUL too.
+    pub fn null_fields(mut self, null_fields: bool) -> Self {
+        self.config.null_fields = null_fields;
+        self.config.null |= null_fields;
+        self
+    }
+
+    /// Separate the file name, line number, column and offset of matching lines and
+    /// counts with this instead of `:`. Context lines keep their `-`.
+    pub fn field_separator(mut self, field_separator: &str) -> Self {
+        self.config.field_separator = field_separator.to_string();
+        self
+    }
+
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.config.quiet = quiet;
+        self
+    }
+
+    /// Print the number of matching lines summed over all files
+    pub fn count_total(mut self, count_total: bool) -> Self {
+        self.config.count_total = count_total;
+        self
+    }
+
+    /// Print each matching line as a JSON object instead of text
+    pub fn json(mut self, json: bool) -> Self {
+        self.config.json = json;
+        self
+    }
+
+    /// Leave files with a zero count out of `count` output
+    pub fn no_zero(mut self, no_zero: bool) -> Self {
+        self.config.no_zero = no_zero;
+        self
+    }
+
+    /// Print `count` output from the highest count down, ties broken by file name,
+    /// once every file has been searched
+    pub fn sort_count(mut self, sort_count: bool) -> Self {
+        self.config.sort_count = sort_count;
+        self
+    }
+
+    /// Count every match rather than matching lines, taking precedence over `count`
+    pub fn count_matches(mut self, count_matches: bool) -> Self {
+        self.config.count_matches = count_matches;
+        self
+    }
+
+    /// Don't print errors about missing or unreadable files; they still set the exit code
+    pub fn no_messages(mut self, no_messages: bool) -> Self {
+        self.config.no_messages = no_messages;
+        self
+    }
+
+    /// Flush output after every line instead of when the buffer fills
+    pub fn line_buffered(mut self, line_buffered: bool) -> Self {
+        self.config.line_buffered = line_buffered;
+        self
+    }
+
+    /// Print matching lines with every match replaced, `$1` naming a capture group
+    pub fn replace(mut self, replace: Option<&str>) -> Self {
+        self.config.replace = replace.map(str::to_string);
+        self
+    }
+
+    /// Rewrite files with the [`replace`](Self::replace) substitutions instead of printing
+    pub fn in_place(mut self, in_place: bool) -> Self {
+        self.config.in_place = in_place;
+        self
+    }
+
+    /// Keep the original of each edited file with this suffix appended
+    pub fn backup(mut self, suffix: Option<&str>) -> Self {
+        self.config.backup = suffix.map(str::to_string);
+        self
+    }
+
+    /// Keep a count of files searched and lines matched on stderr while searching
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.config.progress = progress;
+        self
+    }
+
+    /// Print a summary of the search to stderr when done
+    pub fn stats(mut self, stats: bool) -> Self {
+        self.config.stats = stats;
+        self
+    }
+
+    /// The name printed for standard input instead of `(standard input)`
+    pub fn label(mut self, label: Option<&str>) -> Self {
+        self.config.label = label.map(str::to_string);
+        self
+    }
+
+    /// Print file names with this separator instead of the platform's own
+    pub fn path_separator(mut self, path_separator: Option<char>) -> Self {
+        self.config.path_separator = path_separator;
+        self
+    }
+
+    /// Print each file name once above its lines, with a blank line between files
+    pub fn heading(mut self, heading: bool) -> Self {
+        self.config.heading = heading;
+        self
+    }
+
+    /// Print the 1-based byte column of the first match on each line, or of each -o match
+    pub fn column(mut self, column: bool) -> Self {
+        self.config.column = column;
+        self
+    }
+
+    pub fn byte_offset(mut self, byte_offset: bool) -> Self {
+        self.config.byte_offset = byte_offset;
+        self
+    }
+
+    pub fn binary_files(mut self, binary_files: BinaryFiles) -> Self {
+        self.config.binary_files = binary_files;
+        self
+    }
+
+    /// Search the raw bytes of each file, with Unicode off so `\xFF` is a byte and
+    /// `.` is any byte but a newline. Matches may cross lines, and each is printed
+    /// as its byte offset and a hex dump instead of a line.
+    pub fn byte_search(mut self, byte_search: bool) -> Self {
+        self.config.byte_search = byte_search;
+        self
+    }
+
+    /// Number of worker threads used to search files
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.config.threads = threads.max(1);
+        self
+    }
+
+    /// Have [`run`] stop searching after this long, print what it found so far and
+    /// return 3. Files still being read are cut short, except in `multiline`,
+    /// `file_match` and `byte_search` modes, which match a whole file in one go and
+    /// so only stop between files.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    pub fn mmap(mut self, mmap: MmapMode) -> Self {
+        self.config.mmap = mmap;
+        self
+    }
+
+    /// Search the output of `command FILE` instead of each file itself
+    pub fn pre(mut self, command: Option<&str>) -> Self {
+        self.config.pre = command.map(str::to_string);
+        self
+    }
+
+    /// Decompress gzip files, found by a `.gz` extension or their magic bytes
+    pub fn search_zip(mut self, search_zip: bool) -> Self {
+        self.config.search_zip = search_zip;
+        self
+    }
+
+    /// Experimental: search each regular file in a `.tar` archive as its own file,
+    /// named `archive.tar:member`
+    pub fn search_archive(mut self, search_archive: bool) -> Self {
+        self.config.search_archive = search_archive;
+        self
+    }
+
+    /// Transcode input from this encoding to UTF-8 before searching it
+    pub fn encoding(mut self, encoding: Option<&'static Encoding>) -> Self {
+        self.config.encoding = encoding;
+        self
+    }
+
+    /// Print `\r\n`-terminated lines with a bare `\n`
+    pub fn strip_cr(mut self, strip_cr: bool) -> Self {
+        self.config.strip_cr = strip_cr;
+        self
+    }
+
+    /// Add a newline after a printed last line that has none (on by default)
+    pub fn final_newline(mut self, final_newline: bool) -> Self {
+        self.config.final_newline = final_newline;
+        self
+    }
+
+    pub fn build(self) -> MyResult<Config> {
+        let mut config = self.config;
+        // -i always wins; smart case only kicks in for all-lowercase patterns
+        let insensitive = self.insensitive
+            || (config.smart_case
+                && !self
+                    .patterns
+                    .iter()
+                    .chain(&self.and_patterns)
+                    .chain(&self.not_patterns)
+                    .any(|p| has_uppercase(p, config.fixed_strings)));
+        config.pattern = build_pattern(&self.patterns, insensitive, &config)?;
+        for pattern in &self.and_patterns {
+            let pattern = build_pattern(&[pattern.to_string()], insensitive, &config)?;
+            config.and_patterns.push(pattern);
+        }
+        for pattern in &self.not_patterns {
+            let pattern = build_pattern(&[pattern.to_string()], insensitive, &config)?;
+            config.not_patterns.push(pattern);
+        }
+        config.include = parse_globs(&self.include)?;
+        config.exclude = parse_globs(&self.exclude)?;
+        config.exclude_dir = parse_globs(&self.exclude_dir)?;
+        config.include_dir = parse_globs(&self.include_dir)?;
+        for glob in &self.globs {
+            let (glob, include) = match glob.strip_prefix('!') {
+                Some(glob) => (glob, false),
+                None => (glob.as_str(), true),
+            };
+            let pattern = parse_globs(&[glob.to_string()])?.remove(0);
+            config.globs.push((pattern, include));
+        }
+        Ok(config)
+    }
+}
+
+pub fn get_args() -> MyResult<Config> {
+    let matches = App::new("grepr")
+        .version("0.1.0")
+        .author("Alejandro Martinez <amnaredo@gmail.com>")
+        .about("Rust grep")
+        .arg(
+            Arg::with_name("pattern")
+                .value_name("PATTERN")
+                .help("Search pattern")
+                .required_unless_one(&["regexp", "pattern_file"]),
+        )
+        .arg(
+            Arg::with_name("files")
+                .value_name("FILE")
+                .help("Input file(s)")
+                .required(true)
+                .default_value("-")
+                .min_values(1),
+        )
+        .arg(
+            Arg::with_name("regexp")
+                .value_name("PATTERN")
+                .help("Search pattern (repeatable; all positionals become files)")
+                .short("e")
+                .long("regexp")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("pattern_file")
+                .value_name("FILE")
+                .help("Read patterns from FILE, one per line")
+                .short("f")
+                .long("file")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("and_pattern")
+                .value_name("PATTERN")
+                .help("Only select lines that also match PATTERN (repeatable)")
+                .long("and")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true)
+                .conflicts_with_all(&["multiline", "byte_search"]),
+        )
+        .arg(
+            Arg::with_name("not_pattern")
+                .value_name("PATTERN")
+                .help("Only select lines that don't match PATTERN (repeatable)")
+                .long("not")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true)
+                .conflicts_with_all(&["multiline", "byte_search"]),
+        )
+        .arg(
+            Arg::with_name("files_from")
+                .value_name("FILE")
+                .help("Also search the paths listed in FILE (- for stdin), NUL-separated with -Z")
+                .long("files-from")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("insensitive")
+                .value_name("INSENSITIVE")
+                .help("Case-insensitive")
+                .short("i")
+                .long("insensitive")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("perl_regexp")
+                .value_name("PERL_REGEXP")
+                .help("Patterns are Perl-style regexes with lookaround and backreferences")
+                .short("P")
+                .long("perl-regexp")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("regex_size_limit")
+                .value_name("SIZE")
+                .help("Refuse patterns that compile to more than SIZE bytes (K, M and G suffixes allowed)")
+                .long("regex-size-limit")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dfa_size_limit")
+                .value_name("SIZE")
+                .help("Cap the regex DFA cache at SIZE bytes (K, M and G suffixes allowed)")
+                .long("dfa-size-limit")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("extended_regexp")
+                .value_name("EXTENDED_REGEXP")
+                .help("Patterns are extended regexes (the default)")
+                .short("E")
+                .long("extended-regexp")
+                .takes_value(false)
+                .overrides_with("basic_regexp"),
+        )
+        .arg(
+            Arg::with_name("basic_regexp")
+                .value_name("BASIC_REGEXP")
+                .help("Patterns are POSIX basic regexes, where `\\+` means one or more (no backreferences)")
+                .short("G")
+                .long("basic-regexp")
+                .takes_value(false)
+                .overrides_with("extended_regexp")
+                .conflicts_with_all(&["fixed_strings", "perl_regexp"]),
+        )
+        .arg(
+            Arg::with_name("smart_case")
+                .value_name("SMART_CASE")
+                .help("Case-insensitive unless the pattern has an uppercase letter (long only: -S is --follow)")
+                .long("smart-case")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("fixed_strings")
+                .value_name("FIXED_STRINGS")
+                .help("Treat the pattern as a literal string")
+                .short("F")
+                .long("fixed-strings")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("word_regexp")
+                .value_name("WORD_REGEXP")
+                .help("Match only whole words")
+                .short("w")
+                .long("word-regexp")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("line_regexp")
+                .value_name("LINE_REGEXP")
+                .help("Match only whole lines")
+                .short("x")
+                .long("line-regexp")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("recursive")
+                .value_name("RECURSIVE")
+                .help("Recursive search")
+                .short("r")
+                .long("recursive")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("directories")
+                .value_name("ACTION")
+                .help("Read, skip or recurse into directories named as files (-r is recurse)")
+                .short("d")
+                .long("directories")
+                .possible_values(&["read", "skip", "recurse"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dereference_recursive")
+                .value_name("DEREFERENCE_RECURSIVE")
+                .help("Recursive search, following all symbolic links")
+                .short("R")
+                .long("dereference-recursive")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("include")
+                .value_name("GLOB")
+                .help("Search only files whose name matches GLOB")
+                .long("include")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .value_name("GLOB")
+                .help("Skip files whose name matches GLOB")
+                .long("exclude")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("glob")
+                .value_name("GLOB")
+                .help("Search only files matching GLOB, or skip them if it starts with ! (last match wins)")
+                .short("g")
+                .long("glob")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("exclude_dir")
+                .value_name("GLOB")
+                .help("Skip directories whose name matches GLOB")
+                .long("exclude-dir")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("include_dir")
+                .value_name("GLOB")
+                .help("Only descend into directories whose name matches GLOB (--exclude-dir wins)")
+                .long("include-dir")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("sort")
+                .value_name("BY")
+                .help("Search files sorted by path or modification time")
+                .long("sort")
+                .possible_values(&["path", "modified"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("devices")
+                .value_name("ACTION")
+                .help("Read or skip devices, FIFOs and sockets [default: read unless recursing]")
+                .short("D")
+                .long("devices")
+                .possible_values(&["read", "skip"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_filesize")
+                .value_name("SIZE")
+                .help("Skip files larger than SIZE bytes (K, M and G suffixes allowed)")
+                .long("max-filesize")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("follow")
+                .value_name("FOLLOW")
+                .help("Follow symbolic links when recursing")
+                .short("S")
+                .long("follow")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("max_depth")
+                .value_name("NUM")
+                .help("Descend at most NUM directory levels when recursing")
+                .long("max-depth")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("count")
+                .value_name("COUNT")
+                .help("Count occurrences")
+                .short("c")
+                .long("count")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("count_total")
+                .value_name("COUNT_TOTAL")
+                .help("Print the total count of matching lines across all files")
+                .long("count-total")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("sort_count")
+                .value_name("SORT_COUNT")
+                .help("Print counts from most to fewest once every file is searched (implies -c)")
+                .long("sort-count")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("no_zero")
+                .value_name("NO_ZERO")
+                .help("With -c, don't print counts of zero")
+                .long("no-zero")
+                .takes_value(false)
+                .overrides_with("include_zero"),
+        )
+        .arg(
+            Arg::with_name("include_zero")
+                .value_name("INCLUDE_ZERO")
+                .help("With -c, print counts of zero too (the default)")
+                .long("include-zero")
+                .takes_value(false)
+                .overrides_with("no_zero"),
+        )
+        .arg(
+            Arg::with_name("count_matches")
+                .value_name("COUNT_MATCHES")
+                .help("Print a count of every match, not of matching lines")
+                .long("count-matches")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("no_messages")
+                .value_name("NO_MESSAGES")
+                .help("Suppress error messages about nonexistent or unreadable files")
+                .short("s")
+                .long("no-messages")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("line_buffered")
+                .value_name("LINE_BUFFERED")
+                .help("Flush output after every line")
+                .long("line-buffered")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .value_name("STATS")
+                .help("Print search statistics to stderr when done")
+                .long("stats")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("progress")
+                .value_name("PROGRESS")
+                .help("Show a running count of files searched and matching lines on stderr")
+                .long("progress")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("json")
+                .value_name("JSON")
+                .help("Print matching lines as JSON Lines")
+                .long("json")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("invert")
+                .value_name("INVERT")
+                .help("Invert match")
+                .short("v")
+                .long("invert-match")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("invert_count")
+                .value_name("INVERT_COUNT")
+                .help("Count non-matching lines, same as -c -v")
+                .long("invert-count")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("line_number")
+                .value_name("LINE_NUMBER")
+                .help("Print line numbers")
+                .short("n")
+                .long("line-number")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("max_columns")
+                .value_name("NUM")
+                .help("Cut printed lines to NUM bytes, never splitting a character")
+                .long("max-columns")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trim")
+                .value_name("TRIM")
+                .help("Strip leading whitespace from printed lines")
+                .long("trim")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("column")
+                .value_name("COLUMN")
+                .help("Print the 1-based column of the first match, counted in bytes")
+                .long("column")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("byte_offset")
+                .value_name("BYTE_OFFSET")
+                .help("Print the 0-based byte offset of each line or -o match")
+                .short("b")
+                .long("byte-offset")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("after_context")
+                .value_name("NUM")
+                .help("Print NUM lines of trailing context")
+                .short("A")
+                .long("after-context")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("before_context")
+                .value_name("NUM")
+                .help("Print NUM lines of leading context")
+                .short("B")
+                .long("before-context")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("context")
+                .value_name("NUM")
+                .help("Print NUM lines of leading and trailing context")
+                .short("C")
+                .long("context")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("null_data")
+                .value_name("NULL_DATA")
+                .help("Input and output lines end in NUL bytes, not newlines")
+                .short("z")
+                .long("null-data")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("multiline")
+                .value_name("MULTILINE")
+                .help("Let matches span lines, printing every line a match touches")
+                .short("U")
+                .long("multiline")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("dotall")
+                .value_name("DOTALL")
+                .help("Let . match newlines (requires --multiline)")
+                .long("dotall")
+                .takes_value(false)
+                .requires("multiline"),
+        )
+        .arg(
+            Arg::with_name("group_separator")
+                .value_name("SEP")
+                .help("Print SEP between context groups instead of --")
+                .long("group-separator")
+                .takes_value(true)
+                .overrides_with("no_group_separator"),
+        )
+        .arg(
+            Arg::with_name("no_group_separator")
+                .value_name("NO_GROUP_SEPARATOR")
+                .help("Print nothing between context groups")
+                .long("no-group-separator")
+                .takes_value(false)
+                .overrides_with("group_separator"),
+        )
+        .arg(
+            Arg::with_name("only_matching")
+                .value_name("ONLY_MATCHING")
+                .help("Print only the matched parts of a line")
+                .short("o")
+                .long("only-matching")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("first_match")
+                .value_name("FIRST_MATCH")
+                .help("With -o, print only the first match of each line, not all of them")
+                .long("first-match")
+                .takes_value(false)
+                .requires("only_matching"),
+        )
+        .arg(
+            Arg::with_name("replace")
+                .value_name("TEXT")
+                .help("Print matches replaced by TEXT, where $1 or ${name} is a capture group")
+                .long("replace")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("in_place")
+                .value_name("IN_PLACE")
+                .help("Write the --replace substitutions back to each file instead of printing")
+                .short("I")
+                .long("in-place")
+                .takes_value(false)
+                .requires("replace")
+                .conflicts_with_all(&["encoding", "pre", "search_zip", "invert", "search_archive"]),
+        )
+        .arg(
+            Arg::with_name("backup")
+                .value_name("SUFFIX")
+                .help("With --in-place, keep each original file with SUFFIX appended")
+                .long("backup")
+                .takes_value(true)
+                .requires("in_place"),
+        )
+        .arg(
+            Arg::with_name("with_filename")
+                .value_name("WITH_FILENAME")
+                .help("Print the file name for each match")
+                .short("H")
+                .long("with-filename")
+                .takes_value(false)
+                .overrides_with("no_filename"),
+        )
+        .arg(
+            Arg::with_name("no_filename")
+                .value_name("NO_FILENAME")
+                .help("Suppress the file name prefix on output")
+                .short("h")
+                .long("no-filename")
+                .takes_value(false)
+                .overrides_with("with_filename"),
+        )
+        .arg(
+            Arg::with_name("label")
+                .value_name("NAME")
+                .help("Print NAME for standard input instead of (standard input)")
+                .long("label")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("path_separator")
+                .value_name("CHAR")
+                .help("Print file names with CHAR between path components")
+                .long("path-separator")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("heading")
+                .value_name("HEADING")
+                .help("Print file names above their matches instead of on every line")
+                .long("heading")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("null")
+                .value_name("NULL")
+                .help("Print a NUL byte after file names")
+                .short("Z")
+                .long("null")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("field_separator")
+                .value_name("STRING")
+                .help("Separate the fields of matching lines and counts with STRING [default: :]")
+                .long("field-separator")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("null_fields")
+                .value_name("NULL_FIELDS")
+                .help("End every output field and line with a NUL byte instead of : or a newline")
+                .long("null-fields")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .value_name("QUIET")
+                .help("Print nothing, exit with zero status on the first match")
+                .short("q")
+                .long("quiet")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("files_with_matches")
+                .value_name("FILES_WITH_MATCHES")
+                .help("Print only names of files with matches")
+                .short("l")
+                .long("files-with-matches")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("files_without_match")
+                .value_name("FILES_WITHOUT_MATCH")
+                .help("Print only names of files without matches")
+                .short("L")
+                .long("files-without-match")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("file_match")
+                .value_name("FILE_MATCH")
+                .help("Match each file as one blob, listing files that match (reads whole files into memory)")
+                .long("file-match")
+                .takes_value(false)
+                .conflicts_with_all(&["only_matching", "count_matches", "json", "replace"]),
+        )
+        .arg(
+            Arg::with_name("max_count")
+                .value_name("NUM")
+                .help("Stop after NUM matching lines")
+                .short("m")
+                .long("max-count")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_matches_total")
+                .value_name("NUM")
+                .help("Stop the whole search after NUM matching lines")
+                .long("max-matches-total")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("text")
+                .value_name("TEXT")
+                .help("Process binary files as text, same as --binary-files=text")
+                .short("a")
+                .long("text")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("binary_files")
+                .value_name("TYPE")
+                .help("How to treat binary files")
+                .long("binary-files")
+                .possible_values(&["binary", "text", "without-match"])
+                .default_value("binary"),
+        )
+        .arg(
+            Arg::with_name("byte_search")
+                .value_name("BINARY")
+                .help("Match raw bytes (\\xFF is a byte) and print each match as OFFSET: HEX")
+                .long("binary")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "perl_regexp",
+                    "multiline",
+                    "invert",
+                    "only_matching",
+                    "replace",
+                    "json",
+                    "file_match",
+                    "encoding",
+                    "after_context",
+                    "before_context",
+                    "context",
+                ]),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .value_name("NUM")
+                .help("Number of worker threads [default: number of CPUs]")
+                .long("threads")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .value_name("SECONDS")
+                .help("Stop searching after SECONDS, print what was found and exit with 3")
+                .long("timeout")
+                .takes_value(true)
+                // These match each file in one pass, which can't be cut short
+                .conflicts_with_all(&["multiline", "file_match", "byte_search"]),
+        )
+        .arg(
+            Arg::with_name("pre")
+                .value_name("COMMAND")
+                .help("Search the output of COMMAND FILE instead of each file, e.g. --pre=zcat")
+                .long("pre")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("search_zip")
+                .value_name("SEARCH_ZIP")
+                .help("Search inside gzip-compressed files")
+                .long("search-zip")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("search_archive")
+                .value_name("SEARCH_ARCHIVE")
+                .help("Search each file in .tar archives as ARCHIVE.tar:MEMBER (experimental)")
+                .long("search-archive")
+                .takes_value(false)
+                .conflicts_with("pre"),
+        )
+        .arg(
+            Arg::with_name("encoding")
+                .value_name("ENCODING")
+                .help("Transcode input from ENCODING, e.g. latin1, utf-16le or utf-16be")
+                .long("encoding")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("strip_cr")
+                .value_name("STRIP_CR")
+                .help("Strip the carriage return from CRLF line endings in output")
+                .long("strip-cr")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("no_final_newline")
+                .value_name("NO_FINAL_NEWLINE")
+                .help("Print an unterminated last line as is, without adding a newline")
+                .long("no-final-newline")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("mmap")
+                .value_name("MMAP")
+                .help("Always memory-map regular files")
+                .long("mmap")
+                .takes_value(false)
+                .overrides_with("no_mmap"),
+        )
+        .arg(
+            Arg::with_name("no_mmap")
+                .value_name("NO_MMAP")
+                .help("Never memory-map files")
+                .long("no-mmap")
+                .takes_value(false)
+                .overrides_with("mmap"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .value_name("WHEN")
+                .help("Colorize matches")
+                .long("color")
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::with_name("colors")
+                .value_name("SPEC")
+                .help("Set a color as TYPE:ATTR:COLOR, e.g. match:fg:red (TYPE is match, path or line)")
+                .long("colors")
+                .takes_value(true)
+                .number_of_values(1)
+                .multiple(true),
+        )
+        .get_matches_safe()
+        .or_else(|e| match e.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+            _ => Err(e),
+        })?;
+
+    let mut config = Config::default();
+
+    let insensitive = matches.is_present("insensitive");
+    config.smart_case = matches.is_present("smart_case");
+    config.perl_regexp = matches.is_present("perl_regexp");
+    if let Some(size) = matches.value_of("regex_size_limit") {
+        config.regex_size_limit = Some(parse_size(size, "regex size limit")?);
+    }
+    if let Some(size) = matches.value_of("dfa_size_limit") {
+        config.dfa_size_limit = Some(parse_size(size, "DFA size limit")?);
+    }
+    config.word_regexp = matches.is_present("word_regexp");
+    config.line_regexp = matches.is_present("line_regexp");
+    config.fixed_strings = matches.is_present("fixed_strings");
+    config.basic_regexp = matches.is_present("basic_regexp");
+
+    config.files = matches.values_of_lossy("files").unwrap();
+    let mut patterns = matches.values_of_lossy("regexp").unwrap_or_default();
+    for path in matches.values_of_lossy("pattern_file").unwrap_or_default() {
+        let contents = fs::read_to_string(&path).map_err(|e| GreprError::text(&path, e))?;
+        patterns.extend(
+            contents
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(str::to_string),
+        );
+    }
+    let pattern = matches.value_of("pattern").map(str::to_string);
+    if matches.is_present("regexp") || matches.is_present("pattern_file") {
+        // With -e or -f, the positional pattern is really the first file
+        if let Some(file) = pattern {
+            if matches.occurrences_of("files") == 0 {
+                config.files.clear();
+            }
+            config.files.insert(0, file);
+        }
+    } else {
+        patterns.extend(pattern);
+    }
+    let and_patterns = matches.values_of_lossy("and_pattern").unwrap_or_default();
+    let not_patterns = matches.values_of_lossy("not_pattern").unwrap_or_default();
+    if let Some(path) = matches.value_of("files_from") {
+        // The default stdin entry only stands in for "no files given"
+        if matches.occurrences_of("files") == 0 && config.files == ["-"] {
+            config.files.clear();
+        }
+        let contents = match path {
+            "-" => io::read_to_string(io::stdin()),
+            _ => fs::read_to_string(path),
+        }
+        .map_err(|e| GreprError::text(path, e))?;
+        let sep = if matches.is_present("null") {
+            '\0'
+        } else {
+            '\n'
+        };
+        config.files.extend(
+            contents
+                .split(sep)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string),
+        );
+    }
+
+    let dereference = matches.is_present("dereference_recursive");
+    // -r is --directories=recurse, so whichever comes last wins
+    let recursive = ["recursive", "dereference_recursive"]
+        .iter()
+        .filter_map(|name| matches.index_of(name))
+        .max();
+    config.directories = match matches.value_of("directories") {
+        _ if recursive > matches.index_of("directories") => Directories::Recurse,
+        Some("skip") => Directories::Skip,
+        Some("recurse") => Directories::Recurse,
+        _ => Directories::Read,
+    };
+    config.follow = dereference || matches.is_present("follow");
+    let include = matches.values_of_lossy("include").unwrap_or_default();
+    let exclude = matches.values_of_lossy("exclude").unwrap_or_default();
+    let exclude_dir = matches.values_of_lossy("exclude_dir").unwrap_or_default();
+    let include_dir = matches.values_of_lossy("include_dir").unwrap_or_default();
+    let globs = matches.values_of_lossy("glob").unwrap_or_default();
+    let invert_count = matches.is_present("invert_count");
+    config.count = invert_count || matches.is_present("count");
+    config.count_total = matches.is_present("count_total");
+    config.count_matches = matches.is_present("count_matches");
+    config.sort_count = matches.is_present("sort_count");
+    config.count |= config.sort_count && !config.count_matches;
+    config.no_zero = matches.is_present("no_zero");
+    config.json = matches.is_present("json");
+    config.stats = matches.is_present("stats");
+    config.progress = matches.is_present("progress");
+    config.line_buffered = matches.is_present("line_buffered");
+    config.no_messages = matches.is_present("no_messages");
+    config.invert_match = invert_count || matches.is_present("invert");
+    config.line_number = matches.is_present("line_number");
+    config.byte_offset = matches.is_present("byte_offset");
+    config.column = matches.is_present("column");
+    config.only_matching = matches.is_present("only_matching");
+    config.first_match = matches.is_present("first_match");
+    config.replace = matches.value_of("replace").map(str::to_string);
+    config.in_place = matches.is_present("in_place");
+    config.backup = matches.value_of("backup").map(str::to_string);
+    config.multiline = matches.is_present("multiline");
+    config.null_data = matches.is_present("null_data");
+    config.dotall = matches.is_present("dotall");
+    // Printed lines can't show a match that continues onto the next one
+    if config.multiline && config.replace.is_some() && !config.in_place {
+        return Err(From::from(
+            "--replace can't be combined with --multiline unless editing --in-place",
+        ));
+    }
+    if let Some(sep) = matches.value_of("group_separator") {
+        config.group_separator = Some(sep.to_string());
+    } else if matches.is_present("no_group_separator") {
+        config.group_separator = None;
+    }
+    config.strip_cr = matches.is_present("strip_cr");
+    config.final_newline = !matches.is_present("no_final_newline");
+    config.null_fields = matches.is_present("null_fields");
+    if let Some(sep) = matches.value_of("field_separator") {
+        config.field_separator = sep.to_string();
+    }
+    config.null = config.null_fields || matches.is_present("null");
+    config.heading = matches.is_present("heading");
+    config.label = matches.value_of("label").map(str::to_string);
+    if let Some(sep) = matches.value_of("path_separator") {
+        let mut chars = sep.chars();
+        config.path_separator = match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => {
+                return Err(From::from(format!(
+                    "{}: path separator must be a single character",
+                    sep
+                )))
+            }
+        };
+    }
+    config.quiet = matches.is_present("quiet");
+    if matches.is_present("with_filename") {
+        config.with_filename = WithFilename::Always;
+    } else if matches.is_present("no_filename") {
+        config.with_filename = WithFilename::Never;
+    }
+    config.files_with_matches = matches.is_present("files_with_matches");
+    config.files_without_match = matches.is_present("files_without_match");
+    config.file_match = matches.is_present("file_match");
+    // A whole-file match has no lines to print, so list the file instead
+    if config.file_match && !(config.files_without_match || config.count || config.count_total) {
+        config.files_with_matches = true;
+    }
+    if let Some(num) = matches.value_of("max_count") {
+        config.max_count = Some(
+            num.parse()
+                .map_err(|_| format!("{}: invalid max count", num))?,
+        );
+        // A multiline edit replaces across the whole file at once, not line by line
+        if config.in_place && config.multiline {
+            return Err(From::from(
+                "--max-count can't be combined with --in-place --multiline",
+            ));
+        }
+    }
+    if let Some(num) = matches.value_of("max_matches_total") {
+        config.max_matches_total = Some(
+            num.parse()
+                .map_err(|_| format!("{}: invalid max matches total", num))?,
+        );
+    }
+    config.sort = match matches.value_of("sort") {
+        Some("path") => Some(SortBy::Path),
+        Some("modified") => Some(SortBy::Modified),
+        _ => None,
+    };
+    config.devices = match matches.value_of("devices") {
+        Some("read") => Some(Devices::Read),
+        Some("skip") => Some(Devices::Skip),
+        _ => None,
+    };
+    if let Some(size) = matches.value_of("max_filesize") {
+        config.max_filesize = Some(parse_size(size, "file size")?);
+    }
+    config.trim = matches.is_present("trim");
+    if let Some(num) = matches.value_of("max_columns") {
+        config.max_columns = Some(
+            num.parse()
+                .map_err(|_| format!("{}: invalid max columns", num))?,
+        );
+    }
+    if let Some(num) = matches.value_of("max_depth") {
+        config.max_depth = Some(
+            num.parse()
+                .map_err(|_| format!("{}: invalid max depth", num))?,
+        );
+    }
+    config.binary_files = match matches.value_of("binary_files") {
+        _ if matches.is_present("text") => BinaryFiles::Text,
+        Some("text") => BinaryFiles::Text,
+        Some("without-match") => BinaryFiles::WithoutMatch,
+        _ => BinaryFiles::Binary,
+    };
+    config.byte_search = matches.is_present("byte_search");
+    config.threads = match matches.value_of("threads") {
+        Some(num) => match num.parse() {
+            Ok(threads) if threads > 0 => threads,
+            _ => return Err(From::from(format!("{}: invalid number of threads", num))),
+        },
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    if let Some(secs) = matches.value_of("timeout") {
+        config.timeout = Some(
+            secs.parse()
+                .ok()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .ok_or_else(|| format!("{}: invalid timeout", secs))?,
+        );
+    }
+    config.pre = matches.value_of("pre").map(str::to_string);
+    config.search_zip = matches.is_present("search_zip");
+    config.search_archive = matches.is_present("search_archive");
+    if let Some(name) = matches.value_of("encoding") {
+        config.encoding = Some(
+            Encoding::for_label(name.as_bytes())
+                .ok_or_else(|| format!("{}: unknown encoding", name))?,
+        );
+    }
+    if matches.is_present("mmap") {
+        config.mmap = MmapMode::Always;
+    } else if matches.is_present("no_mmap") {
+        config.mmap = MmapMode::Never;
+    }
+    config.color = match matches.value_of("color") {
+        Some("always") => true,
+        Some("never") => false,
+        _ => io::stdout().is_terminal(),
+    };
+    for spec in matches.values_of_lossy("colors").unwrap_or_default() {
+        parse_color_spec(&mut config.colors, &spec)?;
+    }
+    if let Some(num) = matches.value_of("context") {
+        let context = parse_context(num)?;
+        config.before_context = context;
+        config.after_context = context;
+    }
+    if let Some(num) = matches.value_of("after_context") {
+        config.after_context = parse_context(num)?;
+    }
+    if let Some(num) = matches.value_of("before_context") {
+        config.before_context = parse_context(num)?;
+    }
+
+    ConfigBuilder {
+        patterns,
+        and_patterns,
+        not_patterns,
+        insensitive,
+        include,
+        exclude,
+        exclude_dir,
+        include_dir,
+        globs,
+        config,
+    }
+    .build()
+}
+
+// Uppercase letters, not counting escapes such as `\S` or `\W` in a regex
+fn has_uppercase(pattern: &str, fixed_strings: bool) -> bool {
+    let mut escaped = false;
+    pattern.chars().any(|c| {
+        let upper = !escaped && c.is_uppercase();
+        escaped = !fixed_strings && !escaped && c == '\\';
+        upper
+    })
+}
+
+// Names the first Perl-only construct in a pattern, so a rejected pattern can
+// point at -P instead of leaving the user with a bare parse error. Escapes and
+// bracket expressions are skipped, since `[(?=]` is just three literals.
+fn perl_feature(pattern: &str) -> Option<&'static str> {
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('1'..='9') => return Some("a backreference"),
+                Some('k') if chars.peek() == Some(&'<') => return Some("a named backreference"),
+                _ => {}
+            },
+            '[' => {
+                if chars.peek() == Some(&'^') {
+                    chars.next();
+                }
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                }
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        ']' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '(' if chars.peek() == Some(&'?') => {
+                chars.next();
+                match (chars.next(), chars.peek()) {
+                    (Some('='), _) => return Some("a lookahead"),
+                    (Some('!'), _) => return Some("a negative lookahead"),
+                    (Some('<'), Some('=')) => return Some("a lookbehind"),
+                    (Some('<'), Some('!')) => return Some("a negative lookbehind"),
+                    (Some('>'), _) => return Some("an atomic group"),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Rewrites a POSIX basic regex into the extended syntax the regex crate speaks:
+// `\+ \? \| \( \) \{ \}` become operators, while bare `+ ? | ( ) { }` become
+// literals. A `*` that starts the pattern or a group is literal too. Bracket
+// expressions are copied through untouched, since their contents are literal
+// in both dialects.
+fn bre_to_ere(pattern: &str) -> String {
+    const SPECIAL: &str = "+?|(){}";
+    let mut expr = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    let mut group_start = true;
+    while let Some(c) = chars.next() {
+        let starts_group = match c {
+            '\\' => match chars.next() {
+                Some(next) if SPECIAL.contains(next) => {
+                    expr.push(next);
+                    next == '(' || next == '|'
+                }
+                Some(next) => {
+                    expr.push(c);
+                    expr.push(next);
+                    false
+                }
+                None => {
+                    expr.push(c);
+                    false
+                }
+            },
+            '*' if group_start => {
+                expr.push_str(r"\*");
+                false
+            }
+            '[' => {
+                expr.push(c);
+                // A `]` right after `[` or `[^` is a member, not the end
+                if chars.peek() == Some(&'^') {
+                    expr.extend(chars.next());
+                }
+                if chars.peek() == Some(&']') {
+                    expr.extend(chars.next());
+                }
+                for c in chars.by_ref() {
+                    expr.push(c);
+                    if c == ']' {
+                        break;
+                    }
+                }
+                false
+            }
+            c if SPECIAL.contains(c) => {
+                expr.push('\\');
+                expr.push(c);
+                false
+            }
+            '^' => {
+                expr.push(c);
+                group_start
+            }
+            c => {
+                expr.push(c);
+                false
+            }
+        };
+        group_start = starts_group;
+    }
+    expr
+}
+
+fn build_pattern(patterns: &[String], insensitive: bool, config: &Config) -> MyResult<Matcher> {
+    if config.perl_regexp && config.fixed_strings {
+        return Err(From::from(
+            "--perl-regexp can't be combined with --fixed-strings",
+        ));
+    }
+    if config.perl_regexp && config.multiline {
+        return Err(From::from(
+            "--perl-regexp can't be combined with --multiline",
+        ));
+    }
+    let mut exprs = vec![];
+    for pattern in patterns {
+        // Errors below name `pattern`, which is what the user wrote, not `expr`
+        let expr = if config.fixed_strings {
+            regex::escape(pattern)
+        } else if config.basic_regexp {
+            bre_to_ere(pattern)
+        } else {
+            pattern.to_string()
+        };
+        let valid = if config.perl_regexp {
+            fancy_regex::Regex::new(&expr).is_ok()
+        } else {
+            match regex_builder(&expr, config).build() {
+                Err(regex::Error::CompiledTooBig(limit)) => {
+                    return Err(too_big(&format!("\"{}\"", pattern), limit))
+                }
+                built => built.is_ok(),
+            }
+        };
+        if !valid {
+            let hint = match perl_feature(pattern) {
+                // -P can't take -G patterns, so only the default engine's errors point at it
+                Some(feature) if !config.perl_regexp && !config.basic_regexp => {
+                    format!(": uses {}, which only -P (--perl-regexp) supports", feature)
+                }
+                // POSIX basic regexes have them, but not the engine -G rewrites them for
+                Some("a backreference") if config.basic_regexp => {
+                    ": -G (--basic-regexp) doesn't support backreferences".to_string()
+                }
+                _ => String::new(),
+            };
+            return Err(From::from(format!(
+                "Invalid pattern \"{}\"{}",
+                pattern, hint
+            )));
+        }
+        // The group also scopes inline flags like `(?i)` to their own pattern
+        exprs.push(format!("(?:{})", expr));
+    }
+    // An empty pattern set (e.g. a blank -f file) matches nothing
+    let mut expr = if exprs.is_empty() {
+        r"\b\B".to_string()
+    } else {
+        exprs.join("|")
+    };
+    if config.word_regexp {
+        expr = format!(r"\b(?:{})\b", expr);
+    }
+    if config.line_regexp {
+        expr = format!("^(?:{})$", expr);
+    }
+    if config.perl_regexp {
+        let mut builder = fancy_regex::RegexBuilder::new(&expr);
+        if let Some(limit) = config.regex_size_limit {
+            builder.delegate_size_limit(limit);
+        }
+        if let Some(limit) = config.dfa_size_limit {
+            builder.delegate_dfa_size_limit(limit);
+        }
+        // Every pattern compiled alone without the limits, so failing now is the limit
+        return builder
+            .case_insensitive(insensitive)
+            .build()
+            .map(Matcher::Fancy)
+            .map_err(|e| match config.regex_size_limit {
+                Some(limit) => too_big("All patterns", limit),
+                None => From::from(e),
+            });
+    }
+    // In multiline mode ^ and $ still mean line starts and ends, CRLF included
+    regex_builder(&expr, config)
+        .case_insensitive(insensitive)
+        .multi_line(config.multiline)
+        .crlf(config.multiline)
+        .dot_matches_new_line(config.multiline && config.dotall)
+        .build()
+        .map(Matcher::Regex)
+        .map_err(|e| match e {
+            // Each pattern fit on its own, but together they don't
+            regex::Error::CompiledTooBig(limit) => too_big("All patterns", limit),
+            e => From::from(e),
+        })
+}
+
+// A builder with the settings every pattern shares: the size limits, and Unicode
+// off for --binary so `\xFF` is a byte
+fn regex_builder(expr: &str, config: &Config) -> RegexBuilder {
+    let mut builder = RegexBuilder::new(expr);
+    builder.unicode(!config.byte_search);
+    if let Some(limit) = config.regex_size_limit {
+        builder.size_limit(limit);
+    }
+    if let Some(limit) = config.dfa_size_limit {
+        builder.dfa_size_limit(limit);
+    }
+    builder
+}
+
+fn too_big(what: &str, limit: usize) -> Box<dyn Error> {
+    From::from(format!(
+        "{}: compiled regex is over the size limit of {} bytes (raise it with --regex-size-limit)",
+        what, limit
+    ))
+}
+
+const COLOR_NAMES: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+// Applies one `--colors` spec such as `match:fg:red`; `none` clears a color
+fn parse_color_spec(colors: &mut Colors, spec: &str) -> MyResult<()> {
+    let fields: Vec<_> = spec.split(':').collect();
+    let [kind, attr, value] = fields[..] else {
+        return Err(From::from(format!(
+            "{}: invalid color spec, expected TYPE:ATTR:COLOR",
+            spec
+        )));
+    };
+    let style = match kind {
+        "match" => &mut colors.matched,
+        "path" => &mut colors.path,
+        "line" => &mut colors.line,
+        _ => {
+            return Err(From::from(format!(
+                "{}: unknown color type \"{}\", expected one of: match, path, line",
+                spec, kind
+            )))
+        }
+    };
+    let color = match value {
+        "none" => None,
+        _ => Some(value.parse::<Color>().map_err(|_| {
+            format!(
+                "{}: unknown color \"{}\", expected one of: {}, \"bright \" plus one of those, or none",
+                spec,
+                value,
+                COLOR_NAMES.join(", ")
+            )
+        })?),
+    };
+    match attr {
+        "fg" => style.fg = color,
+        "bg" => style.bg = color,
+        _ => {
+            return Err(From::from(format!(
+                "{}: unknown color attribute \"{}\", expected one of: fg, bg",
+                spec, attr
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn parse_globs(globs: &[String]) -> MyResult<Vec<Pattern>> {
+    globs
+        .iter()
+        .map(|glob| Pattern::new(glob).map_err(|e| From::from(format!("{}: {}", glob, e))))
+        .collect()
+}
+
+// A byte count such as `512`, `64K` or `2G` in powers of 1024, where `what` names
+// it in errors. One too big for `T`, like 8G as a 32-bit usize, is invalid too.
+fn parse_size<T: TryFrom<u64>>(size: &str, what: &str) -> MyResult<T> {
+    let (num, unit) = match size.as_bytes().last() {
+        Some(b'K' | b'k') => (&size[..size.len() - 1], 1 << 10),
+        Some(b'M' | b'm') => (&size[..size.len() - 1], 1 << 20),
+        Some(b'G' | b'g') => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+    num.parse::<u64>()
+        .ok()
+        .and_then(|num| num.checked_mul(unit))
+        .and_then(|num| T::try_from(num).ok())
+        .ok_or_else(|| From::from(format!("{}: invalid {}", size, what)))
+}
+
+fn parse_context(num: &str) -> MyResult<usize> {
+    num.parse()
+        .map_err(|_| From::from(format!("{}: invalid context length argument", num)))
+}
+
+fn find_files(files: &[String], config: &Config) -> Vec<MyResult<String>> {
+    let wanted = |name: &str| {
+        let globbed = match config.globs.iter().rev().find(|(g, _)| g.matches(name)) {
+            Some(&(_, include)) => include,
+            // With any including glob, only files that one matches are searched
+            None => !config.globs.iter().any(|&(_, include)| include),
+        };
+        globbed
+            && (config.include.is_empty() || config.include.iter().any(|g| g.matches(name)))
+            && !config.exclude.iter().any(|g| g.matches(name))
+    };
+    // The starting directory is always entered, only subdirectories are pruned.
+    // Each one on the way down must match an --include-dir glob, if any were
+    // given, and none of the --exclude-dir globs, which win when both match.
+    let pruned = |entry: &DirEntry| {
+        let name = entry.file_name().to_string_lossy();
+        entry.depth() > 0
+            && entry.file_type().is_dir()
+            && (config.exclude_dir.iter().any(|g| g.matches(&name))
+                || (!config.include_dir.is_empty()
+                    && !config.include_dir.iter().any(|g| g.matches(&name))))
+    };
+    // Anything but files, directories and links: devices, FIFOs and sockets, which may
+    // block forever or never end, so a recursive search skips them unless told not to
+    let special = |file_type: fs::FileType| {
+        !file_type.is_file() && !file_type.is_dir() && !file_type.is_symlink()
+    };
+    let mut results = vec![];
+    for path in files {
+        match path.as_str() {
+            "-" => results.push(Ok(path.to_string())),
+            _ => match fs::metadata(path) {
+                Ok(metadata) => {
+                    if metadata.is_dir() {
+                        if config.directories == Directories::Recurse {
+                            let mut walker = WalkDir::new(path).follow_links(config.follow);
+                            if let Some(depth) = config.max_depth {
+                                walker = walker.max_depth(depth);
+                            }
+                            for entry in walker.into_iter().filter_entry(|e| !pruned(e)) {
+                                match entry {
+                                    Ok(entry) => {
+                                        let file_type = entry.file_type();
+                                        let read = file_type.is_file()
+                                            || (special(file_type)
+                                                && config.devices == Some(Devices::Read));
+                                        if read && wanted(&entry.file_name().to_string_lossy()) {
+                                            let path = entry.path().display().to_string();
+                                            push_file(&mut results, path, config);
+                                        }
+                                    }
+                                    // Unreadable entries and link loops are reported, the walk goes on
+                                    Err(e) => {
+                                        let path = e.path().map_or_else(
+                                            || path.to_string(),
+                                            |path| path.display().to_string(),
+                                        );
+                                        let ancestor = e.loop_ancestor().map(|ancestor| {
+                                            format!(
+                                                "File system loop found: points to an ancestor {}",
+                                                ancestor.display()
+                                            )
+                                        });
+                                        let error = match e.into_io_error() {
+                                            Some(source) => GreprError::io(&path, source),
+                                            // Only a link loop has no I/O error behind it
+                                            None => GreprError::Other {
+                                                path,
+                                                source: ancestor.unwrap_or_default().into(),
+                                            },
+                                        };
+                                        results.push(Err(From::from(error)));
+                                    }
+                                }
+                            }
+                        } else if config.directories == Directories::Read {
+                            let path = path.to_string();
+                            results.push(Err(From::from(GreprError::IsDirectory { path })));
+                        }
+                    } else if metadata.is_file()
+                        || (special(metadata.file_type()) && config.devices != Some(Devices::Skip))
+                    {
+                        push_file(&mut results, path.to_string(), config);
+                    }
+                }
+                Err(e) => results.push(Err(From::from(GreprError::io(path, e)))),
+            },
+        }
+    }
+    // Errors have no sort key, so they go first
+    match config.sort {
+        Some(SortBy::Path) => {
+            results.sort_by(|a, b| a.as_ref().ok().cmp(&b.as_ref().ok()));
+        }
+        Some(SortBy::Modified) => results.sort_by_cached_key(|entry| {
+            entry
+                .as_ref()
+                .ok()
+                .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        }),
+        None => {}
+    }
+    results
+}
+
+#[derive(Debug)]
+struct Line {
+    num: usize,
+    offset: usize,
+    text: Vec<u8>,
+    is_match: bool,
+    // With -U -o, the (offset, bytes) of each match starting on this line
+    multiline_matches: Vec<(usize, Vec<u8>)>,
+}
+
+fn find_lines<T: BufRead>(file: T, config: &Config, limit: Option<usize>) -> MyResult<Vec<Line>> {
+    search_lines(file, config, limit).collect()
+}
+
+// The lines selected by `config` with their context, read lazily except in
+// multiline mode, where matches may span lines and the whole input is needed
+fn search_lines<'a, T: BufRead + 'a>(
+    mut file: T,
+    config: &'a Config,
+    limit: Option<usize>,
+) -> Box<dyn Iterator<Item = MyResult<Line>> + 'a> {
+    if !config.multiline {
+        return Box::new(Lines::new(file, config, limit, move |_, line: &[u8]| {
+            config.selects(trim_newline(line, config.null_data)) != config.invert_match
+        }));
+    }
+
+    // Matches may span lines, so search the whole input and mark every line a match touches
+    let mut buf = vec![];
+    if let Err(e) = file.read_to_end(&mut buf) {
+        return Box::new(iter::once(Err(e.into())));
+    }
+    // Lines are NUL-terminated records with -z, just as `Lines` splits them
+    let terminator = if config.null_data { b'\0' } else { b'\n' };
+    let mut starts = vec![0];
+    starts.extend(
+        buf.iter()
+            .enumerate()
+            .filter(|&(i, &b)| b == terminator && i + 1 < buf.len())
+            .map(|(i, _)| i + 1),
+    );
+    let line_of = |pos: usize| starts.partition_point(|&start| start <= pos) - 1;
+    let mut touched = vec![false; if buf.is_empty() { 0 } else { starts.len() }];
+    let mut found = vec![];
+    for Range { start, end } in config.pattern.find_iter(&buf) {
+        // An empty match after the final terminator isn't on any line
+        if start == buf.len() && (buf.is_empty() || buf.ends_with(&[terminator])) {
+            continue;
+        }
+        let last = line_of(end.saturating_sub(1).max(start));
+        for flag in &mut touched[line_of(start)..=last] {
+            *flag = true;
+        }
+        if config.only_matching && start != end {
+            found.push((start, buf[start..end].to_vec()));
+        }
+    }
+    let lines = Lines::new(Cursor::new(buf), config, limit, move |num, _| {
+        touched[num - 1] != config.invert_match
+    });
+    Box::new(lines.map(move |line| {
+        let mut line = line?;
+        if line.is_match && !config.invert_match {
+            let end = line.offset + line.text.len();
+            line.multiline_matches = found
+                .iter()
+                .filter(|(start, _)| (line.offset..end).contains(start))
+                .cloned()
+                .collect();
+        }
+        Ok(line)
+    }))
+}
+
+// Searches `file` as one blob for --file-match, so `^` and `$` are its start and end
+// and `.` can't cross a newline without -U --dotall. A match is reported as a
+// single line with no text, enough for -l, -L, -c and -q. Any --and and --not
+// patterns apply to the whole blob too.
+fn match_file<T: BufRead>(mut file: T, config: &Config) -> MyResult<Vec<Line>> {
+    let mut buf = vec![];
+    file.read_to_end(&mut buf)?;
+    if config.selects(&buf) == config.invert_match {
+        return Ok(vec![]);
+    }
+    Ok(vec![Line {
+        num: 1,
+        offset: 0,
+        text: vec![],
+        is_match: true,
+        multiline_matches: vec![],
+    }])
+}
+
+// With --binary, one line per non-empty match anywhere in `file`, holding the
+// match's offset and bytes; `num` is the match's 1-based position in the file
+fn match_bytes<T: BufRead>(
+    mut file: T,
+    config: &Config,
+    limit: Option<usize>,
+) -> MyResult<Vec<Line>> {
+    let mut buf = vec![];
+    file.read_to_end(&mut buf)?;
+    // Unlike a line's, the input's last newline is fair game
+    Ok(config
+        .pattern
+        .find_iter(&buf)
+        .into_iter()
+        .filter(|span| !span.is_empty())
+        .take(limit.unwrap_or(usize::MAX))
+        .enumerate()
+        .map(|(i, span)| Line {
+            num: i + 1,
+            offset: span.start,
+            text: buf[span].to_vec(),
+            is_match: true,
+            multiline_matches: vec![],
+        })
+        .collect())
+}
+
+// Reads `file` line by line, yielding matches plus the context lines around them
+struct Lines<'a, T, F> {
+    file: T,
+    config: &'a Config,
+    limit: Option<usize>,
+    is_match: F,
+    before: VecDeque<Line>,
+    // Lines already selected but not yet yielded, like the leading context of a match
+    ready: VecDeque<Line>,
+    line_num: usize,
+    offset: usize,
+    num_matches: usize,
+    after_left: usize,
+    done: bool,
+}
+
+impl<'a, T: BufRead, F: FnMut(usize, &[u8]) -> bool> Lines<'a, T, F> {
+    fn new(file: T, config: &'a Config, limit: Option<usize>, is_match: F) -> Self {
+        Lines {
+            file,
+            config,
+            limit,
+            is_match,
+            before: VecDeque::with_capacity(config.before_context),
+            ready: VecDeque::new(),
+            line_num: 0,
+            offset: 0,
+            num_matches: 0,
+            after_left: 0,
+            done: false,
+        }
+    }
+
+    // Reads one line, queueing it in `ready` if it is selected
+    #[allow(clippy::manual_is_multiple_of)] // is_multiple_of needs Rust 1.87
+    fn read_line(&mut self) -> MyResult<()> {
+        let limit_reached = self.limit.is_some_and(|limit| self.num_matches >= limit);
+        if limit_reached && self.after_left == 0 {
+            self.done = true;
+            return Ok(());
+        }
+        let mut text = vec![];
+        let sep = if self.config.null_data { b'\0' } else { b'\n' };
+        let bytes = self.file.read_until(sep, &mut text)?;
+        if bytes == 0 {
+            self.done = true;
+            return Ok(());
+        }
+        self.line_num += 1;
+        // Checked every so often, so a huge file can't outlast --timeout
+        let deadline = self.config.deadline;
+        if self.line_num % 1024 == 0 && deadline.is_some_and(|d| Instant::now() >= d) {
+            self.done = true;
+        }
+        let offset = self.offset;
+        self.offset += bytes;
+        if self.config.strip_cr && text.ends_with(b"\r\n") {
+            text.remove(text.len() - 2);
+        }
+        let is_match = !limit_reached && (self.is_match)(self.line_num, &text);
+        let line = Line {
+            num: self.line_num,
+            offset,
+            text,
+            is_match,
+            multiline_matches: vec![],
+        };
+        let before_context = self.config.before_context;
+        if is_match {
+            self.ready.extend(self.before.drain(..));
+            self.ready.push_back(line);
+            self.num_matches += 1;
+            self.after_left = self.config.after_context;
+        } else if self.after_left > 0 {
+            self.ready.push_back(line);
+            self.after_left -= 1;
+        } else if before_context > 0 {
+            if self.before.len() == before_context {
+                self.before.pop_front();
+            }
+            self.before.push_back(line);
+        }
+        Ok(())
+    }
+}
+
+impl<T: BufRead, F: FnMut(usize, &[u8]) -> bool> Iterator for Lines<'_, T, F> {
+    type Item = MyResult<Line>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.ready.is_empty() && !self.done {
+            if let Err(e) = self.read_line() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        self.ready.pop_front().map(Ok)
+    }
+}
+
+/// A matching line found by [`grep_reader`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// 1-based line number within the input
+    pub line_number: usize,
+    /// 0-based byte offset of the line within the input, as printed by -b
+    pub byte_offset: usize,
+    /// Raw bytes of the line, including its terminator
+    pub text: Vec<u8>,
+    /// Byte ranges of each non-empty match within `text`, found with `find_iter`.
+    ///
+    /// They index the line as read, so `byte_offset + span.start` is what -o -b prints.
+    /// Stripping a `\r` with `strip_cr` only touches the terminator, after every span.
+    pub spans: Vec<Range<usize>>,
+}
+
+/// Searches a reader lazily, a line at a time, for callers that don't want every
+/// match in memory at once
+pub struct Searcher<'a, T> {
+    reader: T,
+    config: &'a Config,
+}
+
+impl<'a> Searcher<'a, Box<dyn BufRead>> {
+    /// Opens `path` to search as the command line would, with `-` for standard input
+    /// and any `pre`, `search_zip` and `encoding` settings applied
+    pub fn open(path: &str, config: &'a Config) -> Result<Self, GreprError> {
+        Ok(Searcher::new(open(path, config)?, config))
+    }
+}
+
+impl<'a, T: BufRead + 'a> Searcher<'a, T> {
+    pub fn new(reader: T, config: &'a Config) -> Self {
+        Searcher { reader, config }
+    }
+
+    /// The lines selected by `config`, as [`grep_reader`] returns them.
+    ///
+    /// Reading stops soon after `max_count` lines have matched. With `multiline`
+    /// the whole input is read before the first match is yielded.
+    pub fn matches(self) -> impl Iterator<Item = MyResult<Match>> + 'a {
+        let config = self.config;
+        search_lines(self.reader, config, config.max_count).filter_map(move |line| match line {
+            Ok(line) if !line.is_match => None,
+            Ok(line) => {
+                let spans = if config.invert_match {
+                    vec![]
+                } else {
+                    config.line_spans(&line.text)
+                };
+                Some(Ok(Match {
+                    line_number: line.num,
+                    byte_offset: line.offset,
+                    text: line.text,
+                    spans,
+                }))
+            }
+            Err(e) => Some(Err(e)),
+        })
+    }
+}
+
+/// Searches `reader` line by line, returning the lines selected by `config`.
+///
+/// Context lines are not included. Inverted matches carry no spans.
+pub fn grep_reader<T: BufRead>(reader: T, config: &Config) -> MyResult<Vec<Match>> {
+    Searcher::new(reader, config).matches().collect()
+}
+
+/// Searches an in-memory buffer like [`grep_reader`], without any file I/O.
+///
+/// Lines are split on `\n`, or on `\0` with `null_data`.
+pub fn search_bytes(data: &[u8], config: &Config) -> MyResult<Vec<Match>> {
+    grep_reader(data, config)
+}
+
+// Byte ranges of the non-empty matches in `text`, a line without its terminator
+fn match_spans(pattern: &Matcher, text: &[u8]) -> Vec<Range<usize>> {
+    let mut spans = pattern.find_iter(text);
+    spans.retain(|span| !span.is_empty());
+    spans
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    path: &'a str,
+    line_number: usize,
+    text: String,
+    submatches: Vec<JsonSubmatch>,
+}
+
+#[derive(Serialize)]
+struct JsonSubmatch {
+    start: usize,
+    end: usize,
+}
+
+// Strips a trailing `\n` or `\r\n`, so `$` and -x work on CRLF files too,
+// or with -z the `\0` that ends each record instead
+fn trim_newline(line: &[u8], null_data: bool) -> &[u8] {
+    if null_data {
+        return line.strip_suffix(b"\0").unwrap_or(line);
+    }
+    line.strip_suffix(b"\r\n")
+        .or_else(|| line.strip_suffix(b"\n"))
+        .unwrap_or(line)
+}
+
+// The spans to color for all of `patterns`, in order; overlapping matches of
+// different patterns merge into one span
+fn merged_spans(patterns: &[&Matcher], text: &[u8]) -> Vec<Range<usize>> {
+    let mut spans: Vec<_> = patterns.iter().flat_map(|p| match_spans(p, text)).collect();
+    spans.sort_by_key(|span| span.start);
+    let mut merged: Vec<Range<usize>> = vec![];
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start < last.end => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+// Colors the given spans of `text`, in order, and leaves the rest plain. Both
+// whole lines and -o substrings are painted here, so they always agree.
+fn paint_spans(
+    text: &[u8],
+    spans: impl IntoIterator<Item = Range<usize>>,
+    style: &Style,
+) -> String {
+    let mut painted = String::new();
+    let mut last = 0;
+    // Zero-width matches have nothing to color
+    for Range { start, end } in spans.into_iter().filter(|span| !span.is_empty()) {
+        painted.push_str(&String::from_utf8_lossy(&text[last..start]));
+        painted.push_str(&style.paint(&String::from_utf8_lossy(&text[start..end])));
+        last = end;
+    }
+    painted.push_str(&String::from_utf8_lossy(&text[last..]));
+    painted
+}
+
+// Cuts `text` to at most `max` bytes for --max-columns, returning what is left and
+// a marker saying how much was dropped. The cut backs off to the start of a UTF-8
+// character, so a multi-byte character is dropped whole rather than split.
+fn clip(text: &[u8], max: Option<usize>) -> (&[u8], String) {
+    match max {
+        Some(max) if text.len() > max => {
+            let mut end = max;
+            while end > 0 && text[end] & 0xc0 == 0x80 {
+                end -= 1;
+            }
+            let marker = format!(" [... {} more bytes]", text.len() - end);
+            (&text[..end], marker)
+        }
+        _ => (text, String::new()),
+    }
+}
+
+// The line with its matches replaced, keeping its terminator
+fn replace(pattern: &Matcher, line: &[u8], rep: &str, null_data: bool) -> Vec<u8> {
+    let (text, eol) = line.split_at(trim_newline(line, null_data).len());
+    let mut replaced = pattern.replace_all(text, rep);
+    replaced.extend_from_slice(eol);
+    replaced
+}
+
+// Like grep, a NUL byte in the first buffer marks the input as binary
+fn is_binary(file: &mut dyn BufRead) -> bool {
+    file.fill_buf().is_ok_and(|buf| buf.contains(&0))
+}
+
+// What searching one file came to, short of an error
+enum Outcome {
+    // The lines to print, and whether the file is binary
+    Lines(Vec<Line>, bool),
+    // Bigger than --max-filesize, which --stats counts as skipped
+    TooBig,
+    // Left out like a file never named: binary with --binary-files=without-match,
+    // or a member gone from its archive
+    Ignored,
+    // Never searched, because --max-matches-total stopped the search first
+    Unsearched,
+}
+
+type Searched = Result<Outcome, String>;
+
+// What a worker tells the printer about a file: with --line-buffered, each line
+// as soon as it's found, and then how the search of the whole file went
+enum Found {
+    Line(Line),
+    Done(Searched),
+}
+
+// Opens and searches one file, returning its lines and whether it looked binary.
+// Given `stream`, the lines of a text file go there as they're read instead, and
+// the search stops early if it returns false.
+fn search_file(
+    filename: &str,
+    config: &Config,
+    limit: Option<usize>,
+    stream: Option<&mut dyn FnMut(Line) -> bool>,
+) -> Searched {
+    if let Some(max) = config.max_filesize {
+        if filename != "-" && fs::metadata(filename).is_ok_and(|m| m.len() > max) {
+            return Ok(Outcome::TooBig);
+        }
+    }
+    let file = open(filename, config).map_err(|e| e.to_string())?;
+    search_reader(filename, file, config, limit, stream)
+}
+
+// Searches an opened file, or an archive member, by the name `filename`
+fn search_reader(
+    filename: &str,
+    mut file: Box<dyn BufRead + '_>,
+    config: &Config,
+    limit: Option<usize>,
+    stream: Option<&mut dyn FnMut(Line) -> bool>,
+) -> Searched {
+    // NULs are the record separator with -z, so they say nothing about binary data,
+    // and --binary is meant for binary data
+    let binary = config.binary_files != BinaryFiles::Text
+        && !config.null_data
+        && !config.byte_search
+        && is_binary(&mut file);
+    if binary && config.binary_files == BinaryFiles::WithoutMatch {
+        return Ok(Outcome::Ignored);
+    }
+    let lines = if config.file_match {
+        match_file(file, config)
+    } else if config.byte_search {
+        match_bytes(file, config, limit)
+    } else if let Some(stream) = stream.filter(|_| !binary) {
+        let mut streamed = Ok(vec![]);
+        for line in search_lines(file, config, limit) {
+            match line.map(&mut *stream) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    streamed = Err(e);
+                    break;
+                }
+            }
+        }
+        streamed
+    } else {
+        find_lines(file, config, limit)
+    }
+    .map_err(|e| format!("{}: {}", filename, e))?;
+    Ok(Outcome::Lines(lines, binary))
+}
+
+// The stdout of a --pre command run on one file. Running out of output waits for
+// the command, and a failed command is an error instead of a quietly short file.
+struct Preprocessed {
+    command: String,
+    child: Child,
+    stdout: ChildStdout,
+    status: Option<ExitStatus>,
+}
+
+impl Preprocessed {
+    fn spawn(command: &str, filename: &str) -> MyResult<Self> {
+        let mut child = Command::new(command)
+            .arg(filename)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{}: {}", command, e))?;
+        let stdout = child.stdout.take().ok_or("no stdout")?;
+        Ok(Preprocessed {
+            command: command.to_string(),
+            child,
+            stdout,
+            status: None,
+        })
+    }
+}
+
+impl Read for Preprocessed {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stdout.read(buf)?;
+        if read > 0 || buf.is_empty() {
+            return Ok(read);
+        }
+        let status = match self.status {
+            Some(status) => status,
+            None => *self.status.insert(self.child.wait()?),
+        };
+        // Every read at the end fails, so a caller that peeked first still sees it
+        if !status.success() {
+            return Err(io::Error::other(format!("{}: {}", self.command, status)));
+        }
+        Ok(0)
+    }
+}
+
+impl Drop for Preprocessed {
+    // A search that stops early, like -l or -m, doesn't need the rest of the output
+    fn drop(&mut self) {
+        if self.status.is_none() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+// Adds a file to search, or with --search-archive each regular file in a .tar
+fn push_file(results: &mut Vec<MyResult<String>>, path: String, config: &Config) {
+    if !config.search_archive || !path.ends_with(".tar") {
+        results.push(Ok(path));
+        return;
+    }
+    let members = || -> io::Result<Vec<String>> {
+        let mut archive = tar::Archive::new(File::open(&path)?);
+        let mut members = vec![];
+        for entry in archive.entries_with_seek()? {
+            let entry = entry?;
+            if entry.header().entry_type().is_file() {
+                members.push(entry.path()?.display().to_string());
+            }
+        }
+        Ok(members)
+    };
+    match members() {
+        Ok(members) => results.extend(
+            members
+                .into_iter()
+                .map(|member| Ok(format!("{}:{}", path, member))),
+        ),
+        Err(e) => results.push(Err(From::from(GreprError::io(&path, e)))),
+    }
+}
+
+// Splits a name made by `push_file` into the archive and the member inside it
+fn archive_member(filename: &str) -> Option<(&str, &str)> {
+    let end = filename.find(".tar:")? + ".tar".len();
+    let archive = &filename[..end];
+    Path::new(archive)
+        .is_file()
+        .then(|| (archive, &filename[end + 1..]))
+}
+
+// The archive an entry from `find_files` is a member of, with --search-archive
+fn archive_of<'a>(entry: &'a Result<String, String>, config: &Config) -> Option<&'a str> {
+    entry
+        .as_deref()
+        .ok()
+        .filter(|_| config.search_archive)
+        .and_then(archive_member)
+        .map(|(archive, _)| archive)
+}
+
+// Searches the `count` members `push_file` listed for `archive` in one read of it,
+// passing `send` each member's position and its streamed lines and result
+fn search_archive(
+    archive: &str,
+    count: usize,
+    config: &Config,
+    limit: Option<usize>,
+    streams: bool,
+    send: &mut dyn FnMut(usize, Found) -> bool,
+) {
+    let mut searched = 0;
+    // The same archive named twice lists its members twice
+    let result = loop {
+        let before = searched;
+        match search_members(archive, count, &mut searched, config, limit, streams, send) {
+            Ok(true) if searched < count && searched > before => {}
+            Ok(true) => break Ok(()),
+            Ok(false) => return,
+            Err(e) => break Err(e),
+        }
+    };
+    // Every member needs a result for the ones after it to print
+    if let Err(e) = result {
+        let error = GreprError::io(archive, e).to_string();
+        searched += 1;
+        if !send(searched - 1, Found::Done(Err(error))) {
+            return;
+        }
+    }
+    for k in searched..count {
+        if !send(k, Found::Done(Ok(Outcome::Ignored))) {
+            return;
+        }
+    }
+}
+
+// One pass of `search_archive`, false once nothing is listening
+fn search_members(
+    archive: &str,
+    count: usize,
+    searched: &mut usize,
+    config: &Config,
+    limit: Option<usize>,
+    streams: bool,
+    send: &mut dyn FnMut(usize, Found) -> bool,
+) -> io::Result<bool> {
+    let mut tar = tar::Archive::new(File::open(archive)?);
+    for entry in tar.entries()? {
+        let entry = entry?;
+        if *searched == count {
+            break;
+        }
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = format!("{}:{}", archive, entry.path()?.display());
+        let k = *searched;
+        *searched += 1;
+        let found = match decode(&name, Box::new(BufReader::new(entry)), config) {
+            Ok(reader) => {
+                let mut stream = |line| send(k, Found::Line(line));
+                let stream = Some(&mut stream as &mut dyn FnMut(Line) -> bool);
+                search_reader(&name, reader, config, limit, stream.filter(|_| streams))
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        if !send(k, Found::Done(found)) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Regular files at least this big are memory-mapped in `MmapMode::Auto`
+const MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+fn open(filename: &str, config: &Config) -> Result<Box<dyn BufRead>, GreprError> {
+    let io_error = |e| GreprError::io(filename, e);
+    let reader: Box<dyn BufRead> = match filename {
+        "-" => Box::new(BufReader::new(io::stdin())),
+        _ if config.pre.is_some() => {
+            let command = config.pre.as_deref().unwrap_or_default();
+            let pre = Preprocessed::spawn(command, filename)
+                .map_err(|e| GreprError::other(filename, e))?;
+            Box::new(BufReader::new(pre))
+        }
+        _ => {
+            let file = File::open(filename).map_err(io_error)?;
+            let metadata = file.metadata().map_err(io_error)?;
+            let use_mmap = metadata.is_file()
+                && match config.mmap {
+                    MmapMode::Auto => metadata.len() >= MMAP_THRESHOLD,
+                    MmapMode::Always => true,
+                    MmapMode::Never => false,
+                };
+            if use_mmap {
+                // SAFETY: the map is only read, but like every mmap-based search tool we
+                // can't stop another process from truncating the file while we scan it
+                let map = unsafe { Mmap::map(&file).map_err(io_error)? };
+                Box::new(Cursor::new(map))
+            } else {
+                Box::new(BufReader::new(file))
+            }
+        }
+    };
+    decode(filename, reader, config)
+}
+
+// Applies the `search_zip` and `encoding` layers to a file's raw bytes
+fn decode<'a>(
+    filename: &str,
+    mut reader: Box<dyn BufRead + 'a>,
+    config: &Config,
+) -> Result<Box<dyn BufRead + 'a>, GreprError> {
+    let io_error = |e| GreprError::io(filename, e);
+    if config.search_zip
+        && (filename.ends_with(".gz")
+            || reader.fill_buf().map_err(io_error)?.starts_with(GZIP_MAGIC))
+    {
+        let mut decoded = BufReader::new(GzDecoder::new(reader));
+        // A bad header shows up on the first read, which the binary check would swallow
+        decoded.fill_buf().map_err(|e| GreprError::Other {
+            path: filename.to_string(),
+            source: format!("corrupt gzip data: {}", e).into(),
+        })?;
+        reader = Box::new(decoded);
+    }
+    // Matching, offsets and output all work on the UTF-8 text, never the raw bytes
+    Ok(match config.encoding {
+        Some(encoding) => Box::new(BufReader::new(
+            DecodeReaderBytesBuilder::new()
+                .encoding(Some(encoding))
+                .build(reader),
+        )),
+        None => reader,
+    })
+}
+
+// Rewrites each file with its matches replaced, returning grep's exit status
+fn edit_files(entries: &[MyResult<String>], rep: &str, config: &Config) -> i32 {
+    let mut matched = false;
+    let mut had_error = false;
+    for entry in entries {
+        let edited = match entry {
+            Ok(filename) => {
+                edit_file(filename, rep, config).map_err(|e| format!("{}: {}", filename, e))
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        match edited {
+            Ok(edited) => matched |= edited,
+            Err(e) => {
+                had_error = true;
+                if !config.no_messages {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+    }
+    if had_error {
+        2
+    } else if matched {
+        0
+    } else {
+        1
+    }
+}
+
+// Replaces the matches in one file, returning whether there were any. The new
+// contents go to a temporary file that is renamed over the original, so an
+// interrupted edit never leaves a half-written file behind.
+fn edit_file(filename: &str, rep: &str, config: &Config) -> MyResult<bool> {
+    if filename == "-" {
+        return Err(From::from("can't edit standard input in place"));
+    }
+    let contents = fs::read(filename)?;
+    if config.binary_files != BinaryFiles::Text && is_binary(&mut Cursor::new(&contents)) {
+        return Err(From::from("binary file, use --text to edit it anyway"));
+    }
+    let edited = if config.multiline {
+        config.pattern.replace_all(&contents, rep)
+    } else {
+        let record_end = if config.null_data { b'\0' } else { b'\n' };
+        // Like the printed preview, only the first -m matching lines are replaced
+        let mut left = config.max_count.unwrap_or(usize::MAX);
+        contents
+            .split_inclusive(|&b| b == record_end)
+            .flat_map(|line| {
+                if left > 0 && config.selects(trim_newline(line, config.null_data)) {
+                    left -= 1;
+                    replace(&config.pattern, line, rep, config.null_data)
+                } else {
+                    line.to_vec()
+                }
+            })
+            .collect()
+    };
+    if edited == contents {
+        return Ok(false);
+    }
+    if let Some(suffix) = &config.backup {
+        fs::copy(filename, format!("{}{}", filename, suffix))?;
+    }
+    let path = Path::new(filename);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.grepr-tmp", name));
+    let result = fs::write(&tmp, &edited)
+        .and_then(|_| fs::set_permissions(&tmp, fs::metadata(filename)?.permissions()))
+        .and_then(|_| fs::rename(&tmp, filename));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result?;
+    Ok(true)
+}
+
+// The name printed for a file, where the `-` pseudo-file is `(standard input)` or its --label,
+// and other paths swap in any --path-separator
+fn display_name<'a>(filename: &'a str, config: &'a Config) -> Cow<'a, str> {
+    match (filename, config.path_separator) {
+        ("-", _) => Cow::Borrowed(config.label.as_deref().unwrap_or("(standard input)")),
+        (_, Some(sep)) if sep != MAIN_SEPARATOR => {
+            Cow::Owned(filename.replace(MAIN_SEPARATOR, sep.encode_utf8(&mut [0; 4])))
+        }
+        _ => Cow::Borrowed(filename),
+    }
+}
+
+// Bounds how far the workers run ahead of the printer, so one slow early file
+// can't leave every later file's results waiting in memory
+struct Window {
+    size: usize,
+    printed: Mutex<usize>,
+    moved: Condvar,
+}
+
+impl Window {
+    fn new(size: usize) -> Window {
+        Window {
+            size,
+            printed: Mutex::new(0),
+            moved: Condvar::new(),
+        }
+    }
+
+    // Blocks until entry `i` is close enough to the next one to print, or the search stops
+    fn wait(&self, i: usize, stop: &AtomicBool) {
+        let printed = self.printed.lock().unwrap();
+        let _printed = self
+            .moved
+            .wait_while(printed, |printed| {
+                i >= printed.saturating_add(self.size) && !stop.load(Ordering::Relaxed)
+            })
+            .unwrap();
+    }
+
+    fn advance(&self, printed: usize) {
+        *self.printed.lock().unwrap() = printed;
+        self.moved.notify_all();
+    }
+}
+
+// Releases every waiting worker when the printer is done, including on an error
+struct Release<'a>(&'a Window, &'a AtomicBool);
+
+impl Drop for Release<'_> {
+    fn drop(&mut self) {
+        self.1.store(true, Ordering::Relaxed);
+        self.0.advance(usize::MAX);
+    }
+}
+
+// The --progress status line, redrawn in place on stderr at most every `INTERVAL`
+#[derive(Default)]
+struct Progress {
+    files: usize,
+    matched_lines: usize,
+    shown: Option<Instant>,
+    width: usize,
+}
+
+impl Progress {
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    fn update(&mut self) {
+        if self
+            .shown
+            .is_some_and(|shown| shown.elapsed() < Self::INTERVAL)
+        {
+            return;
+        }
+        let status = format!(
+            "{} files searched, {} matching lines",
+            self.files, self.matched_lines
+        );
+        eprint!("\r{}", status);
+        self.width = status.len();
+        self.shown = Some(Instant::now());
+    }
+
+    // Blanks the status line, so error messages and the shell prompt start on a clean line
+    fn clear(&mut self) {
+        if self.width > 0 {
+            eprint!("\r{}\r", " ".repeat(self.width));
+            self.width = 0;
+            self.shown = None;
         }
     }
 }
 
-pub fn get_args() -> MyResult<Config> {
-    let matches = App::new("grepr")
-        .version("0.1.0")
-        .author("Alejandro Martinez <amnaredo@gmail.com>")
-        .about("Rust grep")
-        .arg(
-            Arg::with_name("pattern")
-                .value_name("PATTERN")
-                .help("Search pattern")
-                .required(true),
-        )
-        .arg(
-            Arg::with_name("files")
-                .value_name("FILE")
-                .help("Input file(s)")
-                .required(true)
-                .default_value("-")
-                .min_values(1),
-        )
-        .arg(
-            Arg::with_name("insensitive")
-                .value_name("INSENSITIVE")
-                .help("Case-insensitive")
-                .short("i")
-                .long("insensitive")
-                .takes_value(false),
-        )
-        .arg(
-            Arg::with_name("recursive")
-                .value_name("RECURSIVE")
-                .help("Recursive search")
-                .short("r")
-                .long("recursive")
-                .takes_value(false),
-        )
-        .arg(
-            Arg::with_name("count")
-                .value_name("COUNT")
-                .help("Count occurrences")
-                .short("c")
-                .long("count")
-                .takes_value(false),
-        )
-        .arg(
-            Arg::with_name("invert")
-                .value_name("INVERT")
-                .help("Invert match")
-                .short("v")
-                .long("invert-match")
-                .takes_value(false),
-        )
-        .get_matches();
+// What the printer loop does after one result
+enum Flow {
+    Continue,
+    // --max-matches-total is used up, so no more files need searching
+    Stop,
+    // -q found a match, so the search is over with this exit status
+    Exit(i32),
+}
 
-    let mut config = Config::default();
+// Prints results in the order their files were found, keeping the tallies that
+// the exit status, --stats and the other end-of-search summaries need
+struct Printer<'a> {
+    config: &'a Config,
+    out: BufWriter<io::StdoutLock<'static>>,
+    show_filename: bool,
+    matched: bool,
+    had_error: bool,
+    // Matches summed for --count-total
+    total: usize,
+    // Counted as results print, so the files that fill --max-matches-total don't
+    // depend on which thread finished first
+    matches_total: usize,
+    capped: bool,
+    printed_heading: bool,
+    progress: Option<Progress>,
+    searched_files: usize,
+    skipped_files: usize,
+    matched_lines: usize,
+    num_matches: usize,
+    // With --sort-count, every (file, count) until the search is done
+    counts: Vec<(String, usize)>,
+    // The file being printed, whose state below resets when the next one starts
+    file: usize,
+    // So a separator only ever goes between two groups of one file
+    last_num: Option<usize>,
+    file_heading: bool,
+}
 
-    let pattern = matches.value_of("pattern").unwrap();
-    let insensitive = matches.is_present("insensitive");
-    let regex = RegexBuilder::new(pattern)
-        .case_insensitive(insensitive)
-        .build()
-        .map_err(|_| format!("Invalid pattern \"{}\"", pattern))?;
-    config.pattern = regex;
+impl<'a> Printer<'a> {
+    fn new(config: &'a Config, show_filename: bool) -> Self {
+        Printer {
+            config,
+            out: BufWriter::new(io::stdout().lock()),
+            show_filename,
+            matched: false,
+            had_error: false,
+            total: 0,
+            matches_total: 0,
+            capped: false,
+            printed_heading: false,
+            progress: config.progress.then(Progress::default),
+            searched_files: 0,
+            skipped_files: 0,
+            matched_lines: 0,
+            num_matches: 0,
+            counts: vec![],
+            file: 0,
+            last_num: None,
+            file_heading: false,
+        }
+    }
 
-    config.files = matches.values_of_lossy("files").unwrap();
+    // All `file:` and `line:` prefixes go through here so separators stay consistent
+    // A `None` name leaves it out, as under a --heading
+    // `col` is the byte index of a match in its line, printed 1-based by --column
+    // Like grep, context lines separate their fields with `-` where matches use `:`
+    fn prefix(
+        &self,
+        fname: Option<&str>,
+        num: Option<usize>,
+        col: Option<usize>,
+        offset: Option<usize>,
+        context: bool,
+    ) -> String {
+        let config = self.config;
+        let mut prefix = String::new();
+        let sep = match (config.null_fields, context) {
+            (true, _) => "\0",
+            (false, true) => "-",
+            (false, false) => config.field_separator.as_str(),
+        };
+        if let Some(fname) = fname.filter(|_| self.show_filename) {
+            prefix.push_str(&config.colors.path.paint(fname));
+            prefix.push_str(if config.null { "\0" } else { sep });
+        }
+        if let Some(num) = num.filter(|_| config.line_number) {
+            prefix.push_str(&config.colors.line.paint(&num.to_string()));
+            prefix.push_str(sep);
+        }
+        if let Some(col) = col.filter(|_| config.column) {
+            prefix.push_str(&format!("{}{}", col + 1, sep));
+        }
+        if let Some(offset) = offset.filter(|_| config.byte_offset) {
+            prefix.push_str(&format!("{}{}", offset, sep));
+        }
+        prefix
+    }
+
+    fn write_count(&mut self, filename: &str, count: usize) -> io::Result<()> {
+        let prefix = self.prefix(Some(filename), None, None, None, false);
+        let end = if self.config.null_fields { '\0' } else { '\n' };
+        write!(self.out, "{}{}{}", prefix, count, end)
+    }
+
+    // What ends each printed line or -o match: a newline, or with -z or --null-fields a NUL
+    fn record_end(&self) -> char {
+        if self.config.null_data || self.config.null_fields {
+            '\0'
+        } else {
+            '\n'
+        }
+    }
+
+    fn flush_line(&mut self) -> io::Result<()> {
+        if self.config.line_buffered {
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+
+    fn error(&mut self, e: &str) {
+        self.had_error = true;
+        if !self.config.no_messages {
+            if let Some(progress) = &mut self.progress {
+                progress.clear();
+            }
+            eprintln!("{}", e);
+        }
+    }
+
+    // Prints one result for file `i`: a streamed line, or how its search ended
+    fn print(&mut self, i: usize, filename: &str, found: Found) -> io::Result<Flow> {
+        let config = self.config;
+        if self.file != i {
+            (self.file, self.last_num, self.file_heading) = (i, None, false);
+        }
+        let searched = match found {
+            Found::Line(line) => Ok(Outcome::Lines(vec![line], false)),
+            Found::Done(searched) => {
+                let unsearched = matches!(searched, Ok(Outcome::Unsearched));
+                if let Some(progress) = self.progress.as_mut().filter(|_| !unsearched) {
+                    progress.files += 1;
+                }
+                if config.stats && matches!(searched, Ok(Outcome::Lines(..))) {
+                    self.searched_files += 1;
+                }
+                searched
+            }
+        };
+        if self.full() {
+            return self.print_full(filename, searched);
+        }
+        let (mut lines, binary) = match searched {
+            Err(e) => {
+                self.error(&e);
+                return Ok(Flow::Continue);
+            }
+            Ok(Outcome::TooBig) => {
+                self.skipped_files += 1;
+                return Ok(Flow::Continue);
+            }
+            Ok(Outcome::Ignored | Outcome::Unsearched) => return Ok(Flow::Continue),
+            Ok(Outcome::Lines(lines, binary)) => (lines, binary),
+        };
+        self.cap(&mut lines);
+        self.tally(&lines);
+        let found = lines.iter().any(|l| l.is_match);
+        // With -L, success means some file was listed
+        self.matched |= found != config.files_without_match;
+        let eol = if config.null { '\0' } else { '\n' };
+        if config.quiet {
+            if found {
+                return Ok(Flow::Exit(0));
+            }
+        } else if config.files_with_matches {
+            if found {
+                write!(self.out, "{}{}", config.colors.path.paint(filename), eol)?;
+            }
+        } else if config.files_without_match {
+            // Like grep, an empty or unmatched stdin is still listed
+            if !found {
+                write!(self.out, "{}{}", config.colors.path.paint(filename), eol)?;
+            }
+        } else if self.counts() {
+            self.print_count(filename, &lines)?;
+        } else if config.json {
+            self.print_json(filename, &lines)?;
+        } else if config.byte_search {
+            self.print_bytes(filename, &lines)?;
+        } else if binary {
+            if found {
+                writeln!(self.out, "Binary file {} matches", filename)?;
+            }
+        } else {
+            self.print_lines(filename, &lines)?;
+        }
+        self.flush_line()?;
+        // Stopping the moment the cap is reached saves reading files just to skip them
+        Ok(if self.full() {
+            Flow::Stop
+        } else {
+            Flow::Continue
+        })
+    }
+
+    fn full(&self) -> bool {
+        self.config
+            .max_matches_total
+            .is_some_and(|max| self.matches_total >= max)
+    }
+
+    // Whether each file's result is just its count, for -c and the like
+    fn counts(&self) -> bool {
+        let config = self.config;
+        !(config.quiet || config.files_with_matches || config.files_without_match)
+            && (config.count || config.count_total || config.count_matches)
+    }
+
+    // Once --max-matches-total is used up, no more matches print, so results
+    // that only add matches are skipped. Errors still print, and so does every
+    // count, which is zero from then on. Only a match that really was left out
+    // gets the search noted as stopped.
+    fn print_full(&mut self, filename: &str, searched: Searched) -> io::Result<Flow> {
+        match searched {
+            Err(e) => self.error(&e),
+            Ok(Outcome::TooBig) => self.skipped_files += 1,
+            Ok(Outcome::Ignored) => {}
+            Ok(Outcome::Lines(lines, _)) => {
+                self.capped |= lines.iter().any(|l| l.is_match);
+                if self.counts() {
+                    self.print_count(filename, &[])?;
+                }
+            }
+            Ok(Outcome::Unsearched) => {
+                if self.counts() {
+                    self.print_count(filename, &[])?;
+                }
+            }
+        }
+        self.flush_line()?;
+        Ok(Flow::Stop)
+    }
+
+    // Cuts a file off before the first match --max-matches-total has no room for
+    fn cap(&mut self, lines: &mut Vec<Line>) {
+        let Some(max) = self.config.max_matches_total else {
+            return;
+        };
+        let room = max - self.matches_total;
+        let mut seen = 0;
+        let keep = lines
+            .iter()
+            .position(|l| {
+                seen += l.is_match as usize;
+                seen > room
+            })
+            .unwrap_or(lines.len());
+        lines.truncate(keep);
+        self.capped |= seen > room;
+        self.matches_total += seen.min(room);
+    }
+
+    // Counts the matching lines for --progress and --stats
+    fn tally(&mut self, lines: &[Line]) {
+        let config = self.config;
+        if let Some(progress) = &mut self.progress {
+            progress.matched_lines += lines.iter().filter(|l| l.is_match).count();
+            progress.update();
+        }
+        if config.stats {
+            for line in lines.iter().filter(|l| l.is_match) {
+                self.matched_lines += 1;
+                self.num_matches += if config.invert_match {
+                    1
+                } else {
+                    config.line_spans(&line.text).len()
+                };
+            }
+        }
+    }
+
+    fn print_count(&mut self, filename: &str, lines: &[Line]) -> io::Result<()> {
+        let config = self.config;
+        let matching = lines.iter().filter(|l| l.is_match);
+        // With -o, like --count-matches, every match counts rather than every line.
+        // Inverted lines hold no matches, so those are still counted by line.
+        let by_match = config.count_matches || (config.only_matching && !config.first_match);
+        let count = if by_match && !config.invert_match {
+            matching.map(|l| config.line_spans(&l.text).len()).sum()
+        } else {
+            matching.count()
+        };
+        self.total += count;
+        // Every searched file gets a count, even an empty one, unless --no-zero
+        if (config.count || config.count_matches) && (count > 0 || !config.no_zero) {
+            if config.sort_count {
+                self.counts.push((filename.to_string(), count));
+            } else {
+                self.write_count(filename, count)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn print_json(&mut self, filename: &str, lines: &[Line]) -> io::Result<()> {
+        let config = self.config;
+        for line in lines.iter().filter(|l| l.is_match) {
+            let submatches = if config.invert_match {
+                vec![]
+            } else {
+                config
+                    .line_spans(&line.text)
+                    .into_iter()
+                    .map(|Range { start, end }| JsonSubmatch { start, end })
+                    .collect()
+            };
+            let json = JsonLine {
+                path: filename,
+                line_number: line.num,
+                text: String::from_utf8_lossy(&line.text).into_owned(),
+                submatches,
+            };
+            match serde_json::to_string(&json) {
+                Ok(json) => {
+                    writeln!(self.out, "{}", json)?;
+                    self.flush_line()?;
+                }
+                Err(e) => self.error(&format!("{}: {}", filename, e)),
+            }
+        }
+        Ok(())
+    }
+
+    // --binary's matches, as their offset and hex bytes
+    fn print_bytes(&mut self, filename: &str, lines: &[Line]) -> io::Result<()> {
+        for line in lines {
+            let prefix = self.prefix(Some(filename), None, None, None, false);
+            let hex: Vec<_> = line.text.iter().map(|b| format!("{:02x}", b)).collect();
+            writeln!(self.out, "{}{}: {}", prefix, line.offset, hex.join(" "))?;
+            self.flush_line()?;
+        }
+        Ok(())
+    }
+
+    // Selected and context lines, or with -o just their matches
+    fn print_lines(&mut self, filename: &str, lines: &[Line]) -> io::Result<()> {
+        let config = self.config;
+        let heading = config.heading && self.show_filename;
+        let name = Some(filename).filter(|_| !heading);
+        let prints = if !config.only_matching {
+            !lines.is_empty()
+        } else if config.multiline {
+            lines.iter().any(|l| !l.multiline_matches.is_empty())
+        } else {
+            !config.invert_match
+                && lines
+                    .iter()
+                    .any(|l| l.is_match && !config.line_spans(&l.text).is_empty())
+        };
+        if heading && prints && !self.file_heading {
+            if self.printed_heading {
+                writeln!(self.out)?;
+            }
+            writeln!(self.out, "{}", config.colors.path.paint(filename))?;
+            self.printed_heading = true;
+            self.file_heading = true;
+        }
+        for line in lines {
+            if config.only_matching {
+                self.print_matches(name, line)?;
+            } else {
+                self.print_line(name, line)?;
+            }
+        }
+        Ok(())
+    }
+
+    // -o's matches in one line, each on its own
+    fn print_matches(&mut self, name: Option<&str>, line: &Line) -> io::Result<()> {
+        let config = self.config;
+        // How many matches -o prints from each line
+        let per_line = if config.first_match { 1 } else { usize::MAX };
+        let record_end = self.record_end();
+        if config.multiline {
+            for (offset, mat) in line.multiline_matches.iter().take(per_line) {
+                let (mat, marker) = clip(mat, config.max_columns);
+                let mat = paint_spans(mat, iter::once(0..mat.len()), &config.colors.matched);
+                let col = offset - line.offset;
+                let prefix = self.prefix(name, Some(line.num), Some(col), Some(*offset), false);
+                write!(self.out, "{}{}{}{}", prefix, mat, marker, record_end)?;
+                self.flush_line()?;
+            }
+            return Ok(());
+        }
+        if !line.is_match || config.invert_match {
+            return Ok(());
+        }
+        // With --replace, -o prints each match's replacement instead
+        let found: Vec<_> = match &config.replace {
+            Some(rep) => config
+                .pattern
+                .replacements(trim_newline(&line.text, config.null_data), rep)
+                .into_iter()
+                .map(|(span, text)| (span.start, text))
+                .collect(),
+            None => config
+                .line_spans(&line.text)
+                .into_iter()
+                .map(|span| (span.start, line.text[span].to_vec()))
+                .collect(),
+        };
+        for (start, mat) in found.into_iter().take(per_line) {
+            let prefix = self.prefix(
+                name,
+                Some(line.num),
+                Some(start),
+                Some(line.offset + start),
+                false,
+            );
+            let (mat, marker) = clip(&mat, config.max_columns);
+            // Replacements aren't matches, so like whole lines they stay plain
+            let mat = match config.replace {
+                Some(_) => String::from_utf8_lossy(mat).into_owned(),
+                None => paint_spans(mat, iter::once(0..mat.len()), &config.colors.matched),
+            };
+            write!(self.out, "{}{}{}{}", prefix, mat, marker, record_end)?;
+            self.flush_line()?;
+        }
+        Ok(())
+    }
 
-    config.recursive = matches.is_present("recursive");
-    config.count = matches.is_present("count");
-    config.invert_match = matches.is_present("invert");
+    // A whole selected or context line, after any group separator it needs
+    fn print_line(&mut self, name: Option<&str>, line: &Line) -> io::Result<()> {
+        let config = self.config;
+        let record_end = self.record_end();
+        let context = config.before_context > 0 || config.after_context > 0;
+        if let (Some(last), Some(sep)) = (self.last_num, &config.group_separator) {
+            if context && line.num > last + 1 {
+                writeln!(self.out, "{}", sep)?;
+            }
+        }
+        self.last_num = Some(line.num);
+        // Context and inverted lines have no match to give a column
+        let col = if config.column && line.is_match && !config.invert_match {
+            config.line_spans(&line.text).first().map(|span| span.start)
+        } else {
+            None
+        };
+        let prefix = self.prefix(name, Some(line.num), col, Some(line.offset), !line.is_match);
+        write!(self.out, "{}", prefix)?;
+        let (text, eol) = line
+            .text
+            .split_at(trim_newline(&line.text, config.null_data).len());
+        let highlighted = line.is_match && !config.invert_match && config.replace.is_none();
+        // Found on the whole line, so --trim and --max-columns can't change them
+        let spans = if highlighted {
+            let patterns: Vec<_> = iter::once(&config.pattern)
+                .chain(&config.and_patterns)
+                .collect();
+            merged_spans(&patterns, text)
+        } else {
+            vec![]
+        };
+        let mut replaced = match &config.replace {
+            Some(rep) if line.is_match => config.pattern.replace_all(text, rep),
+            _ => text.to_vec(),
+        };
+        // The prefix above already has the column and offset of the whole line
+        let mut trimmed = 0;
+        if config.trim {
+            let indent = replaced.iter().take_while(|b| b.is_ascii_whitespace());
+            trimmed = indent.count();
+            replaced.drain(..trimmed);
+        }
+        // Cut before coloring, so the limit counts text and not escape codes
+        let (text, marker) = clip(&replaced, config.max_columns);
+        if highlighted {
+            let len = text.len();
+            let keep = |pos: usize| pos.saturating_sub(trimmed).min(len);
+            let spans = spans
+                .into_iter()
+                .map(|span| keep(span.start)..keep(span.end));
+            let text = paint_spans(text, spans, &config.colors.matched);
+            write!(self.out, "{}", text)?;
+        } else {
+            write!(self.out, "{}", String::from_utf8_lossy(text))?;
+        }
+        if config.null_fields {
+            // The NUL takes the place of the line's own terminator
+            write!(self.out, "{}{}", marker, record_end)?;
+        } else {
+            write!(self.out, "{}{}", marker, String::from_utf8_lossy(eol))?;
+            // Like grep, end an unterminated last line so output doesn't run on
+            if config.final_newline && !line.text.ends_with(&[record_end as u8]) {
+                write!(self.out, "{}", record_end)?;
+            }
+        }
+        self.flush_line()
+    }
 
-    Ok(config)
+    // The summaries printed once the search is over, returning grep's exit status
+    fn finish(mut self, exit: Option<i32>, timed_out: bool, start: Instant) -> io::Result<i32> {
+        let config = self.config;
+        if let Some(progress) = &mut self.progress {
+            progress.clear();
+        }
+        if config.stats {
+            eprintln!("{} files searched", self.searched_files);
+            eprintln!("{} matched lines", self.matched_lines);
+            eprintln!("{} matches", self.num_matches);
+            if self.skipped_files > 0 {
+                eprintln!("{} files skipped", self.skipped_files);
+            }
+            eprintln!("{:.6}s elapsed", start.elapsed().as_secs_f64());
+        }
+        let mut counts = std::mem::take(&mut self.counts);
+        counts.sort_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+        for (filename, count) in &counts {
+            self.write_count(filename, *count)?;
+        }
+        if self.capped {
+            if let Some(max) = config.max_matches_total {
+                eprintln!(
+                    "search stopped at {} matching {} (--max-matches-total)",
+                    max,
+                    if max == 1 { "line" } else { "lines" }
+                );
+            }
+        }
+        if let Some(code) = exit {
+            self.out.flush()?;
+            return Ok(code);
+        }
+        if timed_out {
+            self.out.flush()?;
+            if let Some(timeout) = config.timeout {
+                eprintln!("search timed out after {}s", timeout.as_secs_f64());
+            }
+            return Ok(3);
+        }
+        if config.count_total {
+            if config.count || config.count_matches {
+                writeln!(self.out, "total{}{}", config.field_separator, self.total)?;
+            } else {
+                writeln!(self.out, "{}", self.total)?;
+            }
+        }
+        self.out.flush()?;
+        Ok(if self.had_error {
+            2
+        } else if self.matched {
+            0
+        } else {
+            1
+        })
+    }
 }
 
-fn find_files(files: &[String], recursive: bool) -> Vec<MyResult<String>> {
-    let mut results = vec![];
-    for path in files {
-        match path.as_str() {
-            "-" => results.push(Ok(path.to_string())),
-            _ => match fs::metadata(&path) {
-                Ok(metadata) => {
-                    if metadata.is_dir() {
-                        if recursive {
-                            for entry in WalkDir::new(path)
-                                .into_iter()
-                                .filter_map(|e| e.ok())
-                                .filter(|e| e.file_type().is_file())
-                            {
-                                results.push(Ok(entry.path().display().to_string()));
-                            }
-                        } else {
-                            results.push(Err(From::from(format!("{} is a directory", path))));
+/// Runs the search, returning grep's exit status: 0 if anything matched, 1 if
+/// nothing did, 2 if a file could not be searched, and 3 if the timeout ran out
+pub fn run(mut config: Config) -> MyResult<i32> {
+    // Escape codes would end up inside the JSON strings
+    control::set_override(config.color && !config.json);
+    let start = Instant::now();
+    config.deadline = config.timeout.map(|timeout| start + timeout);
+    let entries = find_files(&config.files, &config);
+    if let Some(rep) = config.replace.as_ref().filter(|_| config.in_place) {
+        return Ok(edit_files(&entries, rep, &config));
+    }
+    let show_filename = match config.with_filename {
+        WithFilename::Always => true,
+        WithFilename::Never => false,
+        // Paths that failed, like a directory without -r, aren't searched so don't count
+        WithFilename::Auto => entries.iter().filter(|entry| entry.is_ok()).count() > 1,
+    };
+    let limit = if config.quiet || config.files_with_matches || config.files_without_match {
+        Some(config.max_count.unwrap_or(1).min(1))
+    } else {
+        config.max_count
+    };
+    let entries: Vec<_> = entries
+        .into_iter()
+        .map(|entry| entry.map_err(|e| e.to_string()))
+        .collect();
+    // A worker takes all of an archive's members at once, to read the archive just once
+    let mut ends: Vec<_> = (1..=entries.len()).collect();
+    for i in (1..entries.len()).rev() {
+        let archive = archive_of(&entries[i], &config);
+        if archive.is_some() && archive == archive_of(&entries[i - 1], &config) {
+            ends[i - 1] = ends[i];
+        }
+    }
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let timed_out = AtomicBool::new(false);
+    let window = Window::new(2 * config.threads);
+    // --line-buffered prints lines as they're found, unless the output sums up whole files
+    let streams = config.line_buffered
+        && !(config.quiet
+            || config.files_with_matches
+            || config.files_without_match
+            || config.count
+            || config.count_total
+            || config.count_matches);
+    let mut printer = Printer::new(&config, show_filename);
+    let exit = thread::scope(|scope| -> io::Result<_> {
+        let (tx, rx) = mpsc::sync_channel(window.size);
+        for _ in 0..config.threads.min(entries.len()) {
+            let tx = tx.clone();
+            let (entries, ends, next, stop, config) = (&entries, &ends, &next, &stop, &config);
+            let (timed_out, window) = (&timed_out, &window);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    // Also seen after the last file, if searching it ran past the deadline
+                    if config.deadline.is_some_and(|d| Instant::now() >= d) {
+                        timed_out.store(true, Ordering::Relaxed);
+                        stop.store(true, Ordering::Relaxed);
+                        window.advance(usize::MAX);
+                        break;
+                    }
+                    let claimed = next.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |i| {
+                        ends.get(i).copied()
+                    });
+                    let Ok(i) = claimed else {
+                        break;
+                    };
+                    window.wait(i, stop);
+                    // The wait also ends when the search stops, and then `i` isn't wanted
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let entry = &entries[i];
+                    if let Some(archive) = archive_of(entry, config) {
+                        let mut send = |k, found| tx.send((i + k, found)).is_ok();
+                        search_archive(archive, ends[i] - i, config, limit, streams, &mut send);
+                        continue;
+                    }
+                    let mut stream = |line| tx.send((i, Found::Line(line))).is_ok();
+                    let searched = match entry {
+                        Err(e) => Err(e.clone()),
+                        Ok(filename) => {
+                            let stream = Some(&mut stream as &mut dyn FnMut(Line) -> bool);
+                            search_file(filename, config, limit, stream.filter(|_| streams))
                         }
-                    } else if metadata.is_file() {
-                        results.push(Ok(path.to_string()));
+                    };
+                    if tx.send((i, Found::Done(searched))).is_err() {
+                        break;
                     }
                 }
-                Err(e) => results.push(Err(From::from(format!("{}: {}", path, e)))),
-            },
+            });
         }
-    }
-    results
+        drop(tx);
+        let _release = Release(&window, &stop);
+
+        // Results arrive in any order but are printed in discovery order
+        // A file's streamed lines all print before its result, which finishes the file
+        let mut pending: HashMap<_, VecDeque<_>> = HashMap::new();
+        let mut next_print = 0;
+        for (i, found) in rx {
+            pending.entry(i).or_default().push_back(found);
+            while let Some(found) = pending.get_mut(&next_print).and_then(VecDeque::pop_front) {
+                let i = next_print;
+                if let Found::Done(_) = found {
+                    pending.remove(&i);
+                    next_print += 1;
+                    window.advance(next_print);
+                }
+                let name = display_name(entries[i].as_deref().unwrap_or_default(), &config);
+                match printer.print(i, &name, found)? {
+                    Flow::Continue => {}
+                    // Results already on their way still print, as the cap allows
+                    Flow::Stop => {
+                        stop.store(true, Ordering::Relaxed);
+                        window.advance(usize::MAX);
+                    }
+                    Flow::Exit(code) => {
+                        stop.store(true, Ordering::Relaxed);
+                        return Ok(Some(code));
+                    }
+                }
+            }
+        }
+        // The files the cap kept from being searched still get their errors and counts
+        if printer.full() && !timed_out.load(Ordering::Relaxed) {
+            for (i, entry) in entries.iter().enumerate().skip(next_print) {
+                let mut found = pending.remove(&i).unwrap_or_default();
+                if !found.iter().any(|found| matches!(found, Found::Done(_))) {
+                    let unsearched = entry.clone().map(|_| Outcome::Unsearched);
+                    found.push_back(Found::Done(unsearched));
+                }
+                let name = display_name(entry.as_deref().unwrap_or_default(), &config);
+                for found in found {
+                    printer.print(i, &name, found)?;
+                }
+            }
+        }
+        Ok(None)
+    })?;
+    Ok(printer.finish(exit, timed_out.load(Ordering::Relaxed), start)?)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::find_files;
+    use super::{
+        find_files, grep_reader, merged_spans, paint_spans, parse_size, perl_feature, search_bytes,
+        Colors, Config, ConfigBuilder, Directories, GreprError, Match, Matcher, Searcher, Style,
+    };
+    use glob::Pattern;
     use rand::{distributions::Alphanumeric, Rng};
+    use regex::bytes::Regex;
+    use std::fs;
+    use std::io::{self, BufReader, Cursor, Read};
+    use text_colorizer::*;
     #[test]
     fn test_find_files() {
+        let config = Config::default();
         // Verify that the function finds a file known to exist
-        let files = find_files(&["./tests/inputs/fox.txt".to_string()], false);
+        let files = find_files(&["./tests/inputs/fox.txt".to_string()], &config);
         assert_eq!(files.len(), 1);
         assert_eq!(files[0].as_ref().unwrap(), "./tests/inputs/fox.txt");
         // The function should reject a directory without the    recursive option
-        let files = find_files(&["./tests/inputs".to_string()], false);
+        let files = find_files(&["./tests/inputs".to_string()], &config);
         assert_eq!(files.len(), 1);
         if let Err(e) = &files[0] {
-            assert_eq!(e.to_string(), "./tests/inputs is a directory".to_string());
+            assert_eq!(
+                e.to_string(),
+                "./tests/inputs: Is a directory (use -r to search recursively)"
+            );
         }
-        // Verify the function recurses to find four files in the    directory
-        let res = find_files(&["./tests/inputs".to_string()], true);
+        // Verify the function recurses to find six files in the    directory
+        let recursive = Config {
+            directories: Directories::Recurse,
+            ..Default::default()
+        };
+        let res = find_files(&["./tests/inputs".to_string()], &recursive);
         let mut files: Vec<String> = res
             .iter()
             .map(|r| r.as_ref().unwrap().replace("\\", "/"))
             .collect();
         files.sort();
-        assert_eq!(files.len(), 4);
+        assert_eq!(files.len(), 6);
         assert_eq!(
             files,
             vec![
+                "./tests/inputs/binary.bin",
                 "./tests/inputs/bustle.txt",
                 "./tests/inputs/empty.txt",
                 "./tests/inputs/fox.txt",
+                "./tests/inputs/invalid.utf8",
                 "./tests/inputs/nobody.txt",
             ]
         );
@@ -171,84 +4097,319 @@ mod tests {
             .map(char::from)
             .collect();
         // Verify that the function returns the bad file as anerror
-        let files = find_files(&[bad], false);
+        let files = find_files(&[bad], &config);
         assert_eq!(files.len(), 1);
         assert!(files[0].is_err());
+        // Include and exclude globs match against the file name only
+        let filtered = Config {
+            directories: Directories::Recurse,
+            include: vec![Pattern::new("*.txt").unwrap()],
+            exclude: vec![
+                Pattern::new("[bn]*").unwrap(),
+                Pattern::new("inputs").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let res = find_files(&["./tests/inputs".to_string()], &filtered);
+        let mut files: Vec<String> = res
+            .iter()
+            .map(|r| r.as_ref().unwrap().replace("\\", "/"))
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec!["./tests/inputs/empty.txt", "./tests/inputs/fox.txt"]
+        );
     }
-}
 
-fn find_lines<T: BufRead>(
-    mut file: T,
-    pattern: &Regex,
-    invert_match: bool,
-) -> MyResult<Vec<String>> {
-    let mut matches = vec![];
-    let mut line = String::new();
-    loop {
-        let bytes = file.read_line(&mut line)?;
-        if bytes == 0 {
-            break;
+    fn highlight(patterns: &[&Matcher], line: &[u8], style: &Style) -> String {
+        paint_spans(line, merged_spans(patterns, line), style)
+    }
+
+    #[test]
+    fn test_highlight() {
+        control::set_override(true);
+        let style = Colors::default().matched;
+        // Every match on the line is colored, not just the first
+        let pattern = Regex::new("the").unwrap().into();
+        assert_eq!(
+            highlight(&[&pattern], b"the cat and the hat\n", &style),
+            format!("{} cat and {} hat\n", "the".green(), "the".green())
+        );
+        // Adjacent matches are colored individually
+        let pattern = Regex::new("ab").unwrap().into();
+        assert_eq!(
+            highlight(&[&pattern], b"abab", &style),
+            format!("{}{}", "ab".green(), "ab".green())
+        );
+        // Zero-width matches leave the text untouched
+        let pattern = Regex::new("x*").unwrap().into();
+        assert_eq!(highlight(&[&pattern], b"abc", &style), "abc");
+        let pattern = Regex::new("").unwrap().into();
+        assert_eq!(highlight(&[&pattern], b"", &style), "");
+        // Overlapping matches of several patterns make one colored span
+        let first = Regex::new("brown f").unwrap().into();
+        let second = Regex::new("n fox|dog").unwrap().into();
+        assert_eq!(
+            highlight(&[&first, &second], b"brown fox dog", &style),
+            format!("{} {}", "brown fox".green(), "dog".green())
+        );
+        // Other colors come from the style
+        let style = Style {
+            fg: Some(Color::Red),
+            bg: Some(Color::Black),
+        };
+        let pattern = Regex::new("cat").unwrap().into();
+        assert_eq!(
+            highlight(&[&pattern], b"the cat", &style),
+            format!("the {}", "cat".red().on_black())
+        );
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_grep_reader() {
+        let text = "foo bar foo\nbaz\nbarfoo\n";
+        let config = Config {
+            pattern: Regex::new("foo").unwrap().into(),
+            ..Default::default()
+        };
+        let matches = grep_reader(Cursor::new(text), &config).unwrap();
+        assert_eq!(
+            matches,
+            vec![
+                Match {
+                    line_number: 1,
+                    byte_offset: 0,
+                    text: b"foo bar foo\n".to_vec(),
+                    spans: vec![0..3, 8..11],
+                },
+                Match {
+                    line_number: 3,
+                    byte_offset: 16,
+                    text: b"barfoo\n".to_vec(),
+                    spans: vec![3..6],
+                },
+            ]
+        );
+        // Inverted matches keep their original line numbers but no spans
+        let config = Config {
+            invert_match: true,
+            ..config
+        };
+        let matches = grep_reader(Cursor::new(text), &config).unwrap();
+        assert_eq!(
+            matches,
+            vec![Match {
+                line_number: 2,
+                byte_offset: 12,
+                text: b"baz\n".to_vec(),
+                spans: vec![],
+            }]
+        );
+        // Spans index the line as read, CRLF and all
+        let config = Config {
+            pattern: Regex::new("o$").unwrap().into(),
+            strip_cr: true,
+            ..Default::default()
+        };
+        let matches = grep_reader(Cursor::new("\r\nfoo\r\n"), &config).unwrap();
+        assert_eq!(
+            matches,
+            vec![Match {
+                line_number: 2,
+                byte_offset: 2,
+                text: b"foo\n".to_vec(),
+                spans: vec![2..3],
+            }]
+        );
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_search_bytes() {
+        let config = Config {
+            pattern: Regex::new("b.").unwrap().into(),
+            ..Default::default()
+        };
+        let matches = search_bytes(b"abc\nxyz\nbbb", &config).unwrap();
+        assert_eq!(
+            matches,
+            vec![
+                Match {
+                    line_number: 1,
+                    byte_offset: 0,
+                    text: b"abc\n".to_vec(),
+                    spans: vec![1..3],
+                },
+                Match {
+                    line_number: 3,
+                    byte_offset: 8,
+                    text: b"bbb".to_vec(),
+                    spans: vec![0..2],
+                },
+            ]
+        );
+        // The same lines as reading the buffer through `grep_reader`
+        let data = "The bustle\nthe end\n";
+        let config = Config {
+            pattern: Regex::new("(?i)the").unwrap().into(),
+            invert_match: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            search_bytes(data.as_bytes(), &config).unwrap(),
+            grep_reader(Cursor::new(data), &config).unwrap()
+        );
+        assert!(search_bytes(b"", &config).unwrap().is_empty());
+    }
+
+    // An input that never ends, so only a lazy search can finish
+    struct Endless;
+
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let line = b"foo\nbar\n";
+            let len = buf.len().min(line.len());
+            buf[..len].copy_from_slice(&line[..len]);
+            Ok(len)
         }
-        if (pattern.is_match(&line) && !invert_match) || (!pattern.is_match(&line) && invert_match)
-        {
-            matches.push(line.clone());
+    }
+
+    #[test]
+    fn test_searcher() {
+        let config = Config {
+            pattern: Regex::new("bar").unwrap().into(),
+            ..Default::default()
+        };
+        let matches = Searcher::new(BufReader::new(Endless), &config).matches();
+        for (m, line_number) in matches.zip([2, 4, 6]) {
+            let m = m.unwrap();
+            assert_eq!(m.line_number, line_number);
+            assert_eq!(m.text, b"bar\n");
         }
-        line.clear();
+        // max_count ends the iterator without reading to the end
+        let config = Config {
+            max_count: Some(2),
+            before_context: 1,
+            ..config
+        };
+        let matches: Vec<_> = Searcher::new(BufReader::new(Endless), &config)
+            .matches()
+            .map(|m| m.unwrap().line_number)
+            .collect();
+        assert_eq!(matches, vec![2, 4]);
+        // Read errors are yielded, after any lines before them
+        let data = Cursor::new("bar\n").chain(Errors);
+        let mut matches = Searcher::new(BufReader::new(data), &config).matches();
+        assert_eq!(matches.next().unwrap().unwrap().line_number, 1);
+        assert!(matches.next().unwrap().is_err());
+        assert!(matches.next().is_none());
     }
-    Ok(matches)
-}
 
-fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+    struct Errors;
+
+    impl Read for Errors {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("unreadable"))
+        }
     }
-}
 
-pub fn run(config: Config) -> MyResult<()> {
-    let entries = find_files(&config.files, config.recursive);
-    let num_files = &entries.len();
-    let print = |fname: &str, val: &str| {
-        if num_files > &1 {
-            print!("{}:{}", fname, val);
-        } else {
-            print!("{}", val);
+    #[test]
+    fn test_config_builder() {
+        let config = ConfigBuilder::new()
+            .pattern("FOO")
+            .pattern("baz")
+            .insensitive(true)
+            .word_regexp(true)
+            .build()
+            .unwrap();
+        let matches = grep_reader(Cursor::new("foo\nfoobar\nBAZ\n"), &config).unwrap();
+        let lines: Vec<_> = matches.iter().map(|m| m.line_number).collect();
+        assert_eq!(lines, vec![1, 3]);
+        // --and and --not patterns narrow the selection down
+        let config = ConfigBuilder::new()
+            .pattern("foo")
+            .and_pattern("bar")
+            .not_pattern("baz")
+            .build()
+            .unwrap();
+        let matches = grep_reader(Cursor::new("foo\nfoobar\nfoobarbaz\n"), &config).unwrap();
+        let lines: Vec<_> = matches.iter().map(|m| m.line_number).collect();
+        assert_eq!(lines, vec![2]);
+        // The builder validates patterns just like the command line
+        let res = ConfigBuilder::new().pattern("*foo").build();
+        assert_eq!(res.unwrap_err().to_string(), "Invalid pattern \"*foo\"");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size::<u64>("64K", "size").unwrap(), 64 << 10);
+        assert_eq!(parse_size::<u32>("3G", "size").unwrap(), 3 << 30);
+        // Too big for the type, as 4G is for a 32-bit usize, rather than cut short
+        let e = parse_size::<u32>("4G", "regex size limit").unwrap_err();
+        assert_eq!(e.to_string(), "4G: invalid regex size limit");
+    }
+
+    #[test]
+    fn test_perl_feature() {
+        assert_eq!(perl_feature("fox(?= jumps)"), Some("a lookahead"));
+        assert_eq!(perl_feature("fox(?!es)"), Some("a negative lookahead"));
+        assert_eq!(perl_feature("(?<=the )fox"), Some("a lookbehind"));
+        assert_eq!(perl_feature("(?<!the )fox"), Some("a negative lookbehind"));
+        assert_eq!(perl_feature("(?>fo+)x"), Some("an atomic group"));
+        assert_eq!(perl_feature(r"(o)\1"), Some("a backreference"));
+        assert_eq!(perl_feature(r"(?<c>o)\k<c>"), Some("a named backreference"));
+        // Named groups, flags and escaped or bracketed lookalikes are all fine
+        assert_eq!(perl_feature("(?<c>o)(?i:x)"), None);
+        assert_eq!(perl_feature(r"\(?=[(?=]\\1[]\1]"), None);
+    }
+
+    #[test]
+    fn test_grepr_error() {
+        let config = ConfigBuilder::new().pattern("fox").build().unwrap();
+        // A missing file says so, keeping the OS error's text
+        match Searcher::open("./tests/inputs/missing.txt", &config) {
+            Err(e @ GreprError::NotFound { .. }) => {
+                assert_eq!(e.path(), "./tests/inputs/missing.txt");
+                assert!(e.to_string().starts_with("./tests/inputs/missing.txt: "));
+            }
+            other => panic!("expected NotFound, got {:?}", other.err()),
         }
-    };
-    for entry in entries {
-        match entry {
-            Err(e) => eprintln!("{}", e),
-            Ok(filename) => match open(&filename) {
-                Err(e) => eprintln!("{}: {}", filename, e),
-                Ok(file) => match find_lines(file, &config.pattern, config.invert_match) {
-                    Err(e) => eprintln!("{}", e),
-                    Ok(matches) => {
-                        if config.count {
-                            print(&filename, &format!("{}\n", &matches.len()));
-                        } else {
-                            for line in &matches {
-                                if !config.invert_match {
-                                    let mat = config.pattern.find(&line).unwrap();
-                                    let (init, end) = (mat.start(), mat.end());
-
-                                    if num_files > &1 {
-                                        print!("{}: ", filename);
-                                    }
-                                    print!(
-                                        "{}{}{}",
-                                        &line[..init],
-                                        &line[init..end].green(),
-                                        &line[end..]
-                                    );
-                                } else {
-                                    print(&filename, line);
-                                }
-                            }
-                        }
-                    }
-                },
-            },
+        // Opened files search like any other reader
+        let matches: Vec<_> = Searcher::open("./tests/inputs/fox.txt", &config)
+            .unwrap()
+            .matches()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(matches.len(), 1);
+        // find_files boxes the error, which downcasts back to the variant
+        let files = find_files(&["./tests/inputs".to_string()], &config);
+        let e = files[0].as_ref().unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<GreprError>(),
+            Some(GreprError::IsDirectory { .. })
+        ));
+        // So do the errors found while recursing
+        let config = Config {
+            directories: Directories::Recurse,
+            follow: true,
+            ..Config::default()
+        };
+        let files = find_files(&["tests/loop".to_string()], &config);
+        let e = files.iter().find_map(|file| file.as_ref().err()).unwrap();
+        match e.downcast_ref::<GreprError>() {
+            Some(e @ GreprError::Other { .. }) => assert_eq!(e.path(), "tests/loop/self"),
+            other => panic!("expected Other, got {:?}", other),
         }
+        let e = fs::read_to_string("./tests/inputs/invalid.utf8").unwrap_err();
+        assert!(matches!(
+            GreprError::text("./tests/inputs/invalid.utf8", e),
+            GreprError::InvalidUtf8 { .. }
+        ));
+        assert!(matches!(
+            GreprError::io("x", io::Error::from(io::ErrorKind::PermissionDenied)),
+            GreprError::PermissionDenied { .. }
+        ));
     }
-    Ok(())
 }
@@ -1,6 +1,21 @@
+use std::io::{self, Write};
+
 fn main() {
-    if let Err(e) = grepr::get_args().and_then(grepr::run) {
-        eprintln!("{}", e);
-        std::process::exit(1);
+    match grepr::get_args().and_then(grepr::run) {
+        Ok(code) => {
+            io::stdout().flush().ok();
+            std::process::exit(code);
+        }
+        // The reader went away (e.g. `| head`), so there's nobody to tell
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) =>
+        {
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
     }
 }
@@ -1,7 +1,12 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
 use rand::{distributions::Alphanumeric, Rng};
-use std::{fs, path::Path};
+use std::io::{BufRead, BufReader, Write};
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use std::{env, fs, path::Path};
 use sys_info::os_type;
 
 type TestResult = Result<(), Box<dyn std::error::Error>>;
@@ -11,7 +16,24 @@ const BUSTLE: &str = "tests/inputs/bustle.txt";
 const EMPTY: &str = "tests/inputs/empty.txt";
 const FOX: &str = "tests/inputs/fox.txt";
 const NOBODY: &str = "tests/inputs/nobody.txt";
+const INVALID: &str = "tests/inputs/invalid.utf8";
+const BINARY: &str = "tests/inputs/binary.bin";
+const MIXED: &str = "tests/endings/mixed.txt";
+const NO_EOL: &str = "tests/endings/no-eol.txt";
+const NULL_DATA: &str = "tests/endings/null-data.txt";
+const LATIN1: &str = "tests/encodings/latin1.txt";
+const UTF16LE: &str = "tests/encodings/utf16le.txt";
+const UTF16BE: &str = "tests/encodings/utf16be.txt";
 const INPUTS_DIR: &str = "tests/inputs";
+const PATTERNS: &str = "tests/patterns/fox.nobody";
+const TREE_DIR: &str = "tests/tree";
+const LINKS_DIR: &str = "tests/links";
+const LOOP_DIR: &str = "tests/loop";
+const BROKEN_DIR: &str = "tests/broken";
+const UPPER: &str = "tests/pre/upper.sh";
+const SLOW: &str = "tests/pre/slow.sh";
+const FOX_GZ: &str = "tests/zip/fox.txt.gz";
+const NOTES_TAR: &str = "tests/archive/notes.tar";
 
 // --------------------------------------------------
 fn gen_bad_file() -> String {
@@ -42,9 +64,9 @@ fn dies_no_args() -> TestResult {
 #[test]
 fn dies_bad_pattern() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&["*foo", FOX])
+        .args(["*foo", FOX])
         .assert()
-        .failure()
+        .code(2)
         .stderr(predicate::str::contains("Invalid pattern \"*foo\""));
     Ok(())
 }
@@ -55,8 +77,9 @@ fn warns_bad_file() -> TestResult {
     let bad = gen_bad_file();
     let expected = format!("{}: .* [(]os error 2[)]", bad);
     Command::cargo_bin(PRG)?
-        .args(&["foo", &bad])
+        .args(["foo", &bad])
         .assert()
+        .code(2)
         .stderr(predicate::str::is_match(expected)?);
     Ok(())
 }
@@ -72,7 +95,7 @@ fn run(args: &[&str], expected_file: &str) -> TestResult {
         expected_file
     };
 
-    let expected = fs::read_to_string(&expected_file)?;
+    let expected = fs::read_to_string(expected_file)?;
 
     Command::cargo_bin(PRG)?
         .args(args)
@@ -81,6 +104,21 @@ fn run(args: &[&str], expected_file: &str) -> TestResult {
     Ok(())
 }
 
+// --------------------------------------------------
+fn run_unordered(args: &[&str], expected_file: &str) -> TestResult {
+    let expected = fs::read_to_string(expected_file)?;
+    let output = Command::cargo_bin(PRG)?.args(args).output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let mut actual: Vec<_> = stdout.lines().collect();
+    let mut expected: Vec<_> = expected.lines().collect();
+    actual.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(actual, expected);
+    Ok(())
+}
+
 // --------------------------------------------------
 #[test]
 fn empty_file() -> TestResult {
@@ -150,6 +188,15 @@ fn multiple_files_insensitive() -> TestResult {
     )
 }
 
+// --------------------------------------------------
+#[test]
+fn multiple_files_invert() -> TestResult {
+    run(
+        &["-v", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.invert",
+    )
+}
+
 // --------------------------------------------------
 #[test]
 fn recursive() -> TestResult {
@@ -168,6 +215,340 @@ fn recursive_insensitive() -> TestResult {
     )
 }
 
+// --------------------------------------------------
+#[test]
+fn recursive_include_exclude() -> TestResult {
+    run(
+        &[
+            "-ri",
+            "--include=*.txt",
+            "--exclude=b*",
+            "--exclude",
+            "n*",
+            "the",
+            INPUTS_DIR,
+        ],
+        "tests/expected/the.recursive.include.exclude",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_include_only() -> TestResult {
+    run(
+        &[
+            "-r",
+            "--include",
+            "*.utf8",
+            "--include=f*",
+            "line",
+            INPUTS_DIR,
+        ],
+        "tests/expected/invalid.utf8.line.recursive",
+    )
+}
+
+// --------------------------------------------------
+fn globbed(globs: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::cargo_bin(PRG)?
+        .args(["-rl", "--sort=path"])
+        .args(globs)
+        .args(["", INPUTS_DIR])
+        .output()?;
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_glob() -> TestResult {
+    assert_eq!(
+        globbed(&["-g", "*.txt", "--glob=!b*"])?,
+        "tests/inputs/fox.txt\ntests/inputs/nobody.txt\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_glob_exclude_only() -> TestResult {
+    assert_eq!(
+        globbed(&["-g", "!*.txt"])?,
+        "tests/inputs/binary.bin\ntests/inputs/invalid.utf8\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_glob_last_match_wins() -> TestResult {
+    assert_eq!(
+        globbed(&["-g", "*.txt", "-g", "!fox.txt", "-g", "f*"])?,
+        "tests/inputs/bustle.txt\ntests/inputs/fox.txt\ntests/inputs/nobody.txt\n"
+    );
+    assert_eq!(
+        globbed(&["-g", "f*", "-g", "*.txt", "-g", "!fox.txt"])?,
+        "tests/inputs/bustle.txt\ntests/inputs/nobody.txt\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_glob_with_exclude() -> TestResult {
+    assert_eq!(
+        globbed(&["-g", "*.txt", "--exclude=n*"])?,
+        "tests/inputs/bustle.txt\ntests/inputs/fox.txt\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_glob_negated() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-r", "--glob=![", "the", INPUTS_DIR])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("[: Pattern syntax error"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_glob() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-r", "--include=[", "the", INPUTS_DIR])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("[: Pattern syntax error"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_exclude_dir() -> TestResult {
+    run_unordered(
+        &[
+            "-r",
+            "--exclude-dir=target",
+            "--exclude-dir",
+            "v*",
+            "needle",
+            TREE_DIR,
+        ],
+        "tests/expected/needle.tree.exclude-dir",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_include_dir() -> TestResult {
+    run_unordered(
+        &["-r", "--include-dir=src", "needle", TREE_DIR],
+        "tests/expected/needle.tree.include-dir",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_include_dir_nested() -> TestResult {
+    run_unordered(
+        &[
+            "-r",
+            "--include-dir=src",
+            "--include-dir",
+            "nested",
+            "needle",
+            TREE_DIR,
+        ],
+        "tests/expected/needle.tree.include-dir.nested",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_exclude_dir_beats_include_dir() -> TestResult {
+    run_unordered(
+        &[
+            "-r",
+            "--include-dir=*",
+            "--exclude-dir=target",
+            "--exclude-dir=v*",
+            "needle",
+            TREE_DIR,
+        ],
+        "tests/expected/needle.tree.exclude-dir",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_include_dir_root_entered() -> TestResult {
+    // The starting directory is searched even though its name doesn't match
+    run(
+        &["-rH", "--include-dir=nothing", "needle", TREE_DIR],
+        "tests/expected/needle.tree.max-depth1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_max_depth() -> TestResult {
+    run(
+        &["-rH", "--max-depth=1", "needle", TREE_DIR],
+        "tests/expected/needle.tree.max-depth1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_max_depth_exclude_dir() -> TestResult {
+    run_unordered(
+        &[
+            "-r",
+            "--max-depth",
+            "2",
+            "--exclude-dir=target",
+            "needle",
+            TREE_DIR,
+        ],
+        "tests/expected/needle.tree.max-depth2.exclude-dir",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_max_depth_zero() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-r", "--max-depth=0", "needle", TREE_DIR])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_max_depth() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-r", "--max-depth=deep", "needle", TREE_DIR])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("deep: invalid max depth"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_skips_symlinks() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-r", "needle", LINKS_DIR])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_follow() -> TestResult {
+    run_unordered(
+        &["-rS", "needle", LINKS_DIR],
+        "tests/expected/needle.links.follow",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dereference_recursive() -> TestResult {
+    run_unordered(
+        &["-R", "needle", LINKS_DIR],
+        "tests/expected/needle.links.follow",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_follow_loop() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-rH", "--follow", "needle", LOOP_DIR])
+        .assert()
+        .code(2)
+        .stdout("tests/loop/file.txt:needle in loop\n")
+        .stderr(predicate::str::contains("File system loop found"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_reports_walk_errors() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-R", "needle", BROKEN_DIR])
+        .assert()
+        .code(2)
+        .stdout("needle in broken\n")
+        .stderr(predicate::str::contains(
+            "tests/broken/dangling: No such file or directory",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_walk_errors_no_messages() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-Rs", "needle", BROKEN_DIR])
+        .assert()
+        .code(2)
+        .stdout("needle in broken\n")
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_sort_path() -> TestResult {
+    run(
+        &["-r", "--sort=path", "needle", TREE_DIR],
+        "tests/expected/needle.tree.sorted",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn sort_modified() -> TestResult {
+    let dir = env::temp_dir().join(format!("grepr-sort-{}", gen_bad_file()));
+    fs::create_dir(&dir)?;
+    let now = SystemTime::now();
+    for (name, age) in [("a.txt", 10), ("b.txt", 30), ("c.txt", 20)] {
+        let file = fs::File::create(dir.join(name))?;
+        writeln!(&file, "needle in {}", name)?;
+        file.set_modified(now - Duration::from_secs(age))?;
+    }
+    let output = Command::cargo_bin(PRG)?
+        .args(["-rh", "--sort", "modified", "needle"])
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "needle in b.txt\nneedle in c.txt\nneedle in a.txt\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_sort() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--sort=size", "needle", TREE_DIR])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("isn't a valid value"));
+    Ok(())
+}
+
 // --------------------------------------------------
 #[test]
 fn sensitive_count_capital() -> TestResult {
@@ -230,14 +611,3865 @@ fn insensitive_count_multiple() -> TestResult {
 
 // --------------------------------------------------
 #[test]
-fn warns_dir_not_recursive() -> TestResult {
-    let stdout = "tests/inputs/fox.txt:\
-        The quick brown fox jumps over the lazy dog.";
+fn line_number() -> TestResult {
+    run(
+        &["-n", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn line_number_multiple() -> TestResult {
+    run(
+        &["--line-number", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn line_number_invert() -> TestResult {
+    run(
+        &["-nv", "nobody", NOBODY],
+        "tests/expected/nobody.txt.invert.n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn after_context() -> TestResult {
+    run(
+        &["-A", "1", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.A1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn after_context_overlap() -> TestResult {
+    run(
+        &["--after-context", "3", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.A3",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn before_context() -> TestResult {
+    run(
+        &["-B", "1", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.B1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn before_context_overlap() -> TestResult {
+    run(
+        &["--before-context", "3", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.B3",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn before_and_after_context() -> TestResult {
+    run(
+        &["-B", "2", "-A", "1", "public", NOBODY],
+        "tests/expected/nobody.txt.public.B2.A1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn context() -> TestResult {
+    run(
+        &["-C", "1", "public", NOBODY],
+        "tests/expected/nobody.txt.public.C1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn context_specific_wins() -> TestResult {
+    run(
+        &["-B", "2", "--context", "1", "public", NOBODY],
+        "tests/expected/nobody.txt.public.C1.B2",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_after_context() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&["fox", INPUTS_DIR, FOX])
+        .args(["-A", "x", "The", BUSTLE])
         .assert()
-        .stderr(predicate::str::contains("tests/inputs is a directory"))
-        .stdout(predicate::str::contains(stdout));
+        .failure()
+        .stderr(predicate::str::contains(
+            "x: invalid context length argument",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn only_matching() -> TestResult {
+    run(
+        &["-o", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.o",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn only_matching_line_number_multiple() -> TestResult {
+    run(
+        &[
+            "--only-matching",
+            "-n",
+            "[Tt]he",
+            BUSTLE,
+            EMPTY,
+            FOX,
+            NOBODY,
+        ],
+        "tests/expected/all.the.o.n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn color_always() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "fox", FOX])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\u{1b}[32mfox\u{1b}[0m"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn color_never() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color", "never", "fox", FOX])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\u{1b}").not());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn color_only_matching() -> TestResult {
+    for (color, expected) in [
+        ("always", "\u{1b}[32mThe\u{1b}[0m\n".repeat(3)),
+        ("never", "The\n".repeat(3)),
+        // Output to a pipe is never a terminal
+        ("auto", "The\n".repeat(3)),
+    ] {
+        Command::cargo_bin(PRG)?
+            .args([&format!("--color={}", color), "-o", "The", BUSTLE])
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn color_only_matching_matches_line_color() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([
+            "--color=always",
+            "--colors=match:bg:yellow",
+            "-o",
+            "fox",
+            FOX,
+        ])
+        .assert()
+        .success()
+        .stdout("\u{1b}[43;32mfox\u{1b}[0m\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_color() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color=sometimes", "fox", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("isn't a valid value"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn colors_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "--colors=match:fg:red", "fox", FOX])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\u{1b}[31mfox\u{1b}[0m"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn colors_path_and_line() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([
+            "--color=always",
+            "--colors=path:fg:magenta",
+            "--colors",
+            "line:fg:blue",
+            "-Hn",
+            "fox",
+            FOX,
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "\u{1b}[35mtests/inputs/fox.txt\u{1b}[0m:\u{1b}[34m1\u{1b}[0m:",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn colors_none() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "--colors=match:fg:none", "fox", FOX])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\u{1b}").not());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_colors() -> TestResult {
+    for (spec, error) in [
+        ("match:fg:chartreuse", "unknown color \"chartreuse\""),
+        ("title:fg:red", "expected one of: match, path, line"),
+        ("match:style:red", "expected one of: fg, bg"),
+        ("match:red", "expected TYPE:ATTR:COLOR"),
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(["--colors", spec, "fox", FOX])
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains(error));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn invalid_utf8() -> TestResult {
+    run(&["line", INVALID], "tests/expected/invalid.utf8.line")
+}
+
+// --------------------------------------------------
+#[test]
+fn files_with_matches() -> TestResult {
+    run(
+        &["-l", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.l",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn files_with_matches_invert() -> TestResult {
+    run(
+        &[
+            "--files-with-matches",
+            "-v",
+            "nobody",
+            BUSTLE,
+            EMPTY,
+            FOX,
+            NOBODY,
+        ],
+        "tests/expected/all.nobody.lv",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn files_without_match() -> TestResult {
+    run(
+        &["-L", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.L",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn files_without_match_stdin() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--files-without-match", "The", "-"])
+        .write_stdin("")
+        .assert()
+        .stdout("(standard input)\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_count() -> TestResult {
+    run(
+        &["-m", "2", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.m2",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn max_count_count() -> TestResult {
+    run(
+        &["-c", "--max-count", "2", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.m2.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn max_count_invert_count() -> TestResult {
+    run(
+        &["-cv", "-m", "3", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.m3.invert.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_max_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-m", "x", "The", BUSTLE])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("x: invalid max count"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_matches_total() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--max-matches-total=5", "-i", "the", BUSTLE, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{0}:The bustle in a house\r\n\
+             {0}:The morning after death\r\n\
+             {0}:The sweeping up the heart,\r\n\
+             {1}:The quick brown fox jumps over the lazy dog.\r\n\
+             {2}:Then there's a pair of us!\r\n",
+            BUSTLE, FOX, NOBODY
+        ))
+        .stderr("search stopped at 5 matching lines (--max-matches-total)\n");
+    Command::cargo_bin(PRG)?
+        .args(["--max-matches-total=1", "-i", "the", BUSTLE])
+        .assert()
+        .success()
+        .stdout("The bustle in a house\r\n")
+        .stderr("search stopped at 1 matching line (--max-matches-total)\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_matches_total_after_cap() -> TestResult {
+    // Files after the cap still report their errors, and count nothing more
+    Command::cargo_bin(PRG)?
+        .args([
+            "--max-matches-total=1",
+            "-i",
+            "the",
+            FOX,
+            "tests/inputs/missing.txt",
+        ])
+        .assert()
+        .code(2)
+        .stdout("The quick brown fox jumps over the lazy dog.\r\n")
+        .stderr(predicate::str::contains(
+            "tests/inputs/missing.txt: No such file",
+        ));
+    Command::cargo_bin(PRG)?
+        .args([
+            "-c",
+            "--max-matches-total=1",
+            "-i",
+            "the",
+            FOX,
+            EMPTY,
+            BUSTLE,
+        ])
+        .assert()
+        .success()
+        .stdout(format!("{}:1\n{}:0\n{}:0\n", FOX, EMPTY, BUSTLE));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_matches_total_within_file() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--max-matches-total=2", "-c", "-i", "the", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}:2\n{}:0\n", BUSTLE, FOX))
+        .stderr(predicate::str::contains("search stopped at 2"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_matches_total_not_reached() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--max-matches-total=4", "-c", "-i", "the", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}:3\n{}:1\n", BUSTLE, FOX))
+        .stderr("");
+    // Reaching the cap exactly suppresses nothing, however many files follow
+    Command::cargo_bin(PRG)?
+        .args(["--max-matches-total=4", "-i", "the", BUSTLE, FOX, EMPTY])
+        .assert()
+        .success()
+        .stderr("");
+    Command::cargo_bin(PRG)?
+        .args(["--max-matches-total=0", "the", FOX])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_max_matches_total() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--max-matches-total=x", "The", BUSTLE])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("x: invalid max matches total"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn word_regexp() -> TestResult {
+    run(
+        &["-w", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.w",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn word_regexp_alternation_insensitive() -> TestResult {
+    run(
+        &["--word-regexp", "-i", "the|us", NOBODY],
+        "tests/expected/nobody.txt.the.us.wi",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn line_regexp() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-x", "foo"])
+        .write_stdin("foo\nfoobar\nbarfoo\nfoo\n")
+        .assert()
+        .stdout("foo\nfoo\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_regexp_invert_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--line-regexp", "-vc", "foo|bar"])
+        .write_stdin("foo\nfoobar\nbar\nbaz\n")
+        .assert()
+        .stdout("2\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_strings() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-F", "a.b.c"])
+        .write_stdin("a.b.c\naxbxc\n")
+        .assert()
+        .stdout("a.b.c\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_strings_insensitive_line() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--fixed-strings", "-ix", "A+B"])
+        .write_stdin("a+b\naab\nxa+b\n")
+        .assert()
+        .stdout("a+b\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_strings_empty() -> TestResult {
+    run(&["-F", "", FOX], "tests/expected/empty_regex.fox.txt")
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_patterns() -> TestResult {
+    run(
+        &[
+            "-e", "fox", "--regexp", "Nobody", BUSTLE, EMPTY, FOX, NOBODY,
+        ],
+        "tests/expected/all.fox.nobody.e",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_patterns_count() -> TestResult {
+    run(
+        &["-c", "-e", "The", "-e", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.e.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_multiple_patterns() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-e", "fox", "-e", "*foo", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid pattern \"*foo\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn pattern_file() -> TestResult {
+    run(
+        &["-f", PATTERNS, BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.fox.nobody.e",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn pattern_file_and_regexp() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--file", PATTERNS, "-e", "bustle", BUSTLE, FOX])
+        .assert()
+        .stdout(
+            "tests/inputs/bustle.txt:The bustle in a house\r\n\
+            tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\r\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_pattern_file() -> TestResult {
+    let bad = gen_bad_file();
+    let expected = format!("{}: .* [(]os error 2[)]", bad);
+    Command::cargo_bin(PRG)?
+        .args(["-f", &bad, FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::is_match(expected)?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_filename() -> TestResult {
+    run(
+        &["-h", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.h",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn with_filename() -> TestResult {
+    run(
+        &["--with-filename", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.H",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn filename_last_flag_wins() -> TestResult {
+    run(
+        &["-H", "--no-filename", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.h",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn null_files_with_matches() -> TestResult {
+    run(
+        &["-lZ", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.lZ",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn null_files_without_match() -> TestResult {
+    run(
+        &["-L", "--null", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.LZ",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn null_prefix() -> TestResult {
+    run(
+        &["-Z", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.Z",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-q", "The", BUSTLE, EMPTY])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet_no_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--quiet", "zebra", BUSTLE, EMPTY])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet_stops_on_first_match() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["-q", "fox", FOX, &bad])
+        .assert()
+        .success()
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exit_code_match() -> TestResult {
+    Command::cargo_bin(PRG)?.args(["fox", FOX]).assert().code(0);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exit_code_no_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["zebra", FOX])
+        .assert()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exit_code_usage_error() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--no-such-flag", "fox", FOX])
+        .assert()
+        .code(2);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exit_code_help() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("USAGE"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_total() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--count-total", "-i", "the", BUSTLE, EMPTY, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout("7\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_total_with_count() -> TestResult {
+    let expected = fs::read_to_string(
+        "tests/expected/all.the.lowercase.insensitive.count",
+    )?;
+    Command::cargo_bin(PRG)?
+        .args(["-ci", "--count-total", "the", BUSTLE, EMPTY, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout(expected + "total:7\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn byte_offset() -> TestResult {
+    run(
+        &["-b", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.b",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn byte_offset_multibyte_line_number() -> TestResult {
+    run(
+        &["-n", "--byte-offset", "-e", "To", "-e", "How", NOBODY],
+        "tests/expected/nobody.txt.to.how.nb",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn byte_offset_only_matching() -> TestResult {
+    run(&["-ob", "Nobody", NOBODY], "tests/expected/nobody.txt.ob")
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_file_matches() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["data", BINARY, FOX])
+        .assert()
+        .success()
+        .stdout("Binary file tests/inputs/binary.bin matches\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_files_text() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--binary-files=text", "data", BINARY])
+        .assert()
+        .success()
+        .stdout("some\0binary\0data\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_files_without_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--binary-files", "without-match", "data", BINARY])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn text_binary_file() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-a", "data", BINARY])
+        .assert()
+        .success()
+        .stdout("some\0binary\0data\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn text_binary_stdin() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--text", "data"])
+        .write_stdin("no match\nraw\0data\n")
+        .assert()
+        .success()
+        .stdout("raw\0data\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_stdin() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .arg("data")
+        .write_stdin("no match\nraw\0data\n")
+        .assert()
+        .success()
+        .stdout("Binary file (standard input) matches\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn threads_keep_file_order() -> TestResult {
+    run(
+        &["--threads", "4", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn threads_slow_first_file() -> TestResult {
+    // Later files wait on the slow first one rather than all being held in memory
+    let files = [NOBODY, FOX, FOX, FOX, FOX, FOX, FOX, FOX];
+    let mut args = vec!["--threads=2", "--pre", SLOW, "-c", "o"];
+    args.extend(files);
+    let expected =
+        format!("{}:8\n{}", NOBODY, format!("{}:1\n", FOX).repeat(7));
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn single_thread() -> TestResult {
+    run(
+        &["--threads=1", "-ci", "the", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.lowercase.insensitive.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_threads() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--threads", "0", "The", BUSTLE])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("0: invalid number of threads"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn mmap() -> TestResult {
+    run(
+        &["--mmap", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn mmap_offsets() -> TestResult {
+    run(
+        &["--mmap", "-nb", "-e", "To", "-e", "How", NOBODY],
+        "tests/expected/nobody.txt.to.how.nb",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn no_mmap() -> TestResult {
+    run(
+        &[
+            "--mmap",
+            "--no-mmap",
+            "-c",
+            "The",
+            BUSTLE,
+            EMPTY,
+            FOX,
+            NOBODY,
+        ],
+        "tests/expected/all.the.capitalized.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn crlf_line_regexp() -> TestResult {
+    run(&["-x", "apple", MIXED], "tests/expected/mixed.txt.apple.x")
+}
+
+// --------------------------------------------------
+#[test]
+fn lf_line_regexp() -> TestResult {
+    run(&["-x", "grape", MIXED], "tests/expected/mixed.txt.grape.x")
+}
+
+// --------------------------------------------------
+#[test]
+fn mixed_endings_anchor() -> TestResult {
+    run(&["e$", MIXED], "tests/expected/mixed.txt.e.anchor")
+}
+
+// --------------------------------------------------
+#[test]
+fn strip_cr() -> TestResult {
+    run(
+        &["--strip-cr", "e$", MIXED],
+        "tests/expected/mixed.txt.e.anchor.strip-cr",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn json() -> TestResult {
+    run(&["--json", "fox", FOX], "tests/expected/fox.txt.json")
+}
+
+// --------------------------------------------------
+#[test]
+fn json_ignores_color() -> TestResult {
+    run(
+        &[
+            "--json",
+            "--color=always",
+            "-e",
+            "Nobody",
+            "-e",
+            " are ",
+            BUSTLE,
+            EMPTY,
+            FOX,
+            NOBODY,
+        ],
+        "tests/expected/all.nobody.are.json",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn json_invert() -> TestResult {
+    run(
+        &["--json", "-v", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.v.json",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_zero_for_empty_and_unmatched() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "fox", EMPTY, BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}:0\n{}:0\n{}:1\n", EMPTY, BUSTLE, FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_no_zero() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "--no-zero", "fox", EMPTY, BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}:1\n", FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_no_zero_nothing_found() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "--no-zero", "fox", EMPTY, BUSTLE])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_include_zero() -> TestResult {
+    for args in [
+        &["--include-zero"][..],
+        &["--no-zero", "--include-zero"],
+        &["--include-zero", "--no-zero", "--include-zero"],
+    ] {
+        Command::cargo_bin(PRG)?
+            .arg("-c")
+            .args(args)
+            .args(["fox", EMPTY, BUSTLE, FOX])
+            .assert()
+            .success()
+            .stdout(format!("{}:0\n{}:0\n{}:1\n", EMPTY, BUSTLE, FOX));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_no_zero_last_wins() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "--include-zero", "--no-zero", "fox", EMPTY, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}:1\n", FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_only_matching() -> TestResult {
+    for args in [&["-oc"][..], &["-c", "--only-matching"]] {
+        Command::cargo_bin(PRG)?
+            .args(args)
+            .args(["fox", "-"])
+            .write_stdin("fox and fox\nno match\nfox\n")
+            .assert()
+            .success()
+            .stdout("3\n");
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_only_matching_twice_on_one_line() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-oc", "the", FOX])
+        .assert()
+        .success()
+        .stdout("1\n");
+    Command::cargo_bin(PRG)?
+        .args(["-oci", "the", FOX])
+        .assert()
+        .success()
+        .stdout("2\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_only_matching_inverted() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-ocv", "fox", "-"])
+        .write_stdin("fox and fox\nno match\n")
+        .assert()
+        .success()
+        .stdout("1\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn sort_count() -> TestResult {
+    // Looking in a scrambled order, with threads finishing in any order
+    run(
+        &["--sort-count", "-i", "the", NOBODY, FOX, EMPTY, BUSTLE],
+        "tests/expected/all.the.lowercase.insensitive.sort-count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn sort_count_no_zero_matches() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([
+            "--sort-count",
+            "--count-matches",
+            "--no-zero",
+            "--count-total",
+        ])
+        .args(["-i", "the", FOX, EMPTY, NOBODY, BUSTLE])
+        .assert()
+        .success()
+        .stdout(format!("{}:4\n{}:4\n{}:2\ntotal:10\n", BUSTLE, NOBODY, FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_matches() -> TestResult {
+    run(
+        &["--count-matches", "-i", "the", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.insensitive.count-matches",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_matches_overrides_count() -> TestResult {
+    run(
+        &[
+            "-c",
+            "--count-matches",
+            "-i",
+            "the",
+            BUSTLE,
+            EMPTY,
+            FOX,
+            NOBODY,
+        ],
+        "tests/expected/all.the.insensitive.count-matches",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_matches_total() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--count-matches", "--count-total", "-i", "the", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/bustle.txt:4\ntests/inputs/fox.txt:2\ntotal:6\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_buffered() -> TestResult {
+    run(
+        &["--line-buffered", "-n", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn line_buffered_only_matching() -> TestResult {
+    run(
+        &["--line-buffered", "-ob", "Nobody", NOBODY],
+        "tests/expected/nobody.txt.ob",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn line_buffered_streams_stdin() -> TestResult {
+    // The pipe stays open, so the match can only arrive if it isn't held until EOF
+    let mut child =
+        std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+            .args(["--line-buffered", "fox"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+    let mut stdin = child.stdin.take().ok_or("no stdin")?;
+    writeln!(stdin, "the fox")?;
+    let stdout = child.stdout.take().ok_or("no stdout")?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut line = String::new();
+        let _ = BufReader::new(stdout).read_line(&mut line);
+        let _ = tx.send(line);
+    });
+    let first = rx.recv_timeout(Duration::from_secs(5));
+    drop(stdin);
+    child.wait()?;
+    assert_eq!(first?, "the fox\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stats() -> TestResult {
+    let expected =
+        fs::read_to_string("tests/expected/all.the.lowercase.insensitive")?;
+    Command::cargo_bin(PRG)?
+        .args(["--stats", "-i", "the", BUSTLE, EMPTY, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout(expected)
+        .stderr(predicate::str::starts_with(
+            "4 files searched\n7 matched lines\n10 matches\n",
+        ))
+        .stderr(predicate::str::is_match("s elapsed\n$")?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_filesize() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--max-filesize=100", "The", BUSTLE, EMPTY, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout(
+            "tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\r\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_filesize_suffix() -> TestResult {
+    run(
+        &["--max-filesize", "1K", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn max_filesize_stats() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([
+            "--stats",
+            "--max-filesize=100",
+            "-i",
+            "the",
+            BUSTLE,
+            FOX,
+            NOBODY,
+        ])
+        .assert()
+        .success()
+        .stderr(predicate::str::starts_with("1 files searched\n"))
+        .stderr(predicate::str::contains("\n2 files skipped\n"));
+    // Only the size limit skips files, not the binary check
+    Command::cargo_bin(PRG)?
+        .args(["--stats", "--binary-files=without-match", "-i", "the"])
+        .args([BINARY, BUSTLE])
+        .assert()
+        .success()
+        .stderr(predicate::str::starts_with("1 files searched\n"))
+        .stderr(predicate::str::contains("skipped").not());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_max_filesize() -> TestResult {
+    for size in ["10X", "abc", "M", "1.5M"] {
+        Command::cargo_bin(PRG)?
+            .args(["--max-filesize", size, "The", FOX])
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains(format!(
+                "{}: invalid file size",
+                size
+            )));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_stats_by_default() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-i", "the", BUSTLE, EMPTY, FOX, NOBODY])
+        .assert()
+        .success()
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_from_stdin() -> TestResult {
+    let expected = fs::read_to_string("tests/expected/all.the.capitalized")?;
+    Command::cargo_bin(PRG)?
+        .args(["--files-from=-", "The"])
+        .write_stdin(format!("{}\n{}\n\n{}\n{}\n", BUSTLE, EMPTY, FOX, NOBODY))
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_from_null_separated() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-lZ", "--files-from", "-", "The"])
+        .write_stdin(format!("{}\0{}\0{}\0", BUSTLE, EMPTY, FOX))
+        .assert()
+        .success()
+        .stdout(format!("{}\0{}\0", BUSTLE, FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_from_with_files() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--files-from=-", "-c", "The", BUSTLE])
+        .write_stdin(format!("{}\n", FOX))
+        .assert()
+        .success()
+        .stdout(format!("{}:3\n{}:1\n", BUSTLE, FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_from_checks_paths() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["--files-from=-", "The"])
+        .write_stdin(format!("{}\n{}\n", bad, INPUTS_DIR))
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(format!("{}: ", bad)))
+        .stderr(predicate::str::contains("tests/inputs: Is a directory"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_files_from() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["--files-from", &bad, "The"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(format!("{}: ", bad)));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn adds_final_newline() -> TestResult {
+    run(
+        &["-B1", "last", NO_EOL],
+        "tests/expected/no-eol.txt.last.B1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn adds_final_newline_inverted() -> TestResult {
+    run(
+        &["-v", "first", NO_EOL],
+        "tests/expected/no-eol.txt.first.v",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn no_final_newline() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--no-final-newline", "last", NO_EOL])
+        .assert()
+        .success()
+        .stdout("last line");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn group_separator() -> TestResult {
+    run(
+        &["-A", "1", "--group-separator=##", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.A1.group-separator",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn no_group_separator() -> TestResult {
+    run(
+        &["-A", "1", "--no-group-separator", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.A1.no-group-separator",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn group_separator_last_wins() -> TestResult {
+    run(
+        &[
+            "-A1",
+            "--no-group-separator",
+            "--group-separator=--",
+            "The",
+            BUSTLE,
+        ],
+        "tests/expected/bustle.txt.the.capitalized.A1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn perl_lookahead() -> TestResult {
+    run(
+        &["-P", "fox(?= jumps)", FOX],
+        "tests/expected/fox.txt.P.lookahead",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn perl_backreference() -> TestResult {
+    run(
+        &["--perl-regexp", "-n", r"(\w)\1", BUSTLE, NOBODY],
+        "tests/expected/bustle.nobody.P.backref.n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn perl_only_matching() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-Po", r"(\w)\1", NOBODY])
+        .assert()
+        .success()
+        .stdout("oo\nll\nll\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn lookahead_needs_perl() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["fox(?= jumps)", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "Invalid pattern \"fox(?= jumps)\": uses a lookahead, \
+             which only -P (--perl-regexp) supports",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn backreference_needs_perl() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([r"(o)\1", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "uses a backreference, which only -P (--perl-regexp) supports",
+        ));
+    Command::cargo_bin(PRG)?
+        .args(["-P", r"(o)\1", FOX])
+        .assert()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_perl_with_other_engine_flags() -> TestResult {
+    for (flag, error) in [
+        ("-F", "--perl-regexp can't be combined with --fixed-strings"),
+        ("-U", "--perl-regexp can't be combined with --multiline"),
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(["-P", flag, "fox", FOX])
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains(error));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn basic_regexp_operators() -> TestResult {
+    for (pattern, expected) in [
+        (r"o\{2\}", "2:oo\n"),
+        (r"Are\|Frog", "2:Are\n7:Frog\n"),
+        (r"l\+", "4:ll\n7:l\n7:l\n8:ll\n8:l\n8:l\n"),
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(["-G", "-on", pattern, NOBODY])
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn basic_regexp_literals() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--basic-regexp", "e+", NOBODY])
+        .assert()
+        .code(1)
+        .stdout("");
+    Command::cargo_bin(PRG)?
+        .args(["-G", "-o", "(x)+", "-"])
+        .write_stdin("f(x)+g(x)\n")
+        .assert()
+        .success()
+        .stdout("(x)+\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn extended_regexp_last_wins() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-G", "-E", "-on", "o{2}", NOBODY])
+        .assert()
+        .success()
+        .stdout("2:oo\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_basic_regexp() -> TestResult {
+    // The error quotes the pattern as written, not its extended rewrite
+    Command::cargo_bin(PRG)?
+        .args(["-G", r"a\(b", FOX])
+        .assert()
+        .code(2)
+        .stderr("Invalid pattern \"a\\(b\"\n");
+    // Nor does it suggest -P, which can't be combined with -G
+    Command::cargo_bin(PRG)?
+        .args(["-G", r"\(o\)\1", FOX])
+        .assert()
+        .code(2)
+        .stderr(
+            "Invalid pattern \"\\(o\\)\\1\": \
+             -G (--basic-regexp) doesn't support backreferences\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn smart_case_lowercase() -> TestResult {
+    run(
+        &["--smart-case", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn smart_case_uppercase() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--smart-case", "THE", BUSTLE])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn smart_case_ignores_escapes() -> TestResult {
+    run(
+        &["--smart-case", r"the\W", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn insensitive_beats_smart_case() -> TestResult {
+    run(
+        &["-i", "--smart-case", "THE", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn inline_insensitive() -> TestResult {
+    run(
+        &["(?i)THE", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn inline_sensitive_overrides_i() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-i", "(?-i)THE", BUSTLE])
+        .assert()
+        .code(1)
+        .stdout("");
+    run(
+        &["-i", "(?-i)The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn inline_flags_stay_in_their_pattern() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-e", "(?i)NOBODY", "-e", "ARE", NOBODY])
+        .assert()
+        .success()
+        .stdout("I'm Nobody! Who are you?\r\nAre you—Nobody—too?\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline() -> TestResult {
+    run(
+        &["-U", r"house\r\nThe", BUSTLE],
+        "tests/expected/bustle.txt.multiline",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline_context() -> TestResult {
+    run(
+        &["--multiline", "-n", "-A1", r"house\r\nThe", BUSTLE],
+        "tests/expected/bustle.txt.multiline.n.A1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-U", "-c", r"death\r\nIs|heart", BUSTLE])
+        .assert()
+        .success()
+        .stdout("3\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline_only_matching() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-U", "-onb", r"house\r\nThe", BUSTLE])
+        .assert()
+        .success()
+        .stdout("1:16:house\r\nThe\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline_crlf_anchors() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-U", "-n", "earth,—$|^$", BUSTLE])
+        .assert()
+        .success()
+        .stdout("4:Enacted upon earth,—\r\n5:\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline_dotall() -> TestResult {
+    run(
+        &["--multiline", "--dotall", "house..The", BUSTLE],
+        "tests/expected/bustle.txt.multiline",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline_dot_stops_at_newline() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-U", "house..The", BUSTLE])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_dotall_without_multiline() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--dotall", "house..The", BUSTLE])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("--multiline"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_multiline_without_flag() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([r"house\r\nThe", BUSTLE])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn heading() -> TestResult {
+    run(
+        &["--heading", "-n", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.heading.n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn heading_only_matching() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--heading", "-o", "fox|Nobody", BUSTLE, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout(format!("{}\nfox\n\n{}\nNobody\nNobody\n", FOX, NOBODY));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn heading_single_file() -> TestResult {
+    run(
+        &["--heading", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn heading_with_filename() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--heading", "-H", "fox", FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\nThe quick brown fox jumps over the lazy dog.\r\n",
+            FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_data() -> TestResult {
+    run(
+        &["-z", "pie", NULL_DATA],
+        "tests/expected/null-data.txt.pie.z",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn null_data_count() -> TestResult {
+    run(
+        &["-zc", "pie", NULL_DATA],
+        "tests/expected/null-data.txt.pie.zc",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn null_data_only_matching() -> TestResult {
+    run(
+        &["--null-data", "-ob", "pie", NULL_DATA],
+        "tests/expected/null-data.txt.pie.zob",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn null_data_invert() -> TestResult {
+    run(
+        &["-zvn", "pie", NULL_DATA],
+        "tests/expected/null-data.txt.pie.zvn",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn null_data_anchor() -> TestResult {
+    run(
+        &["-z", "split$", NULL_DATA],
+        "tests/expected/null-data.txt.split.z",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn null_data_multiline() -> TestResult {
+    // Records, not lines, are what a match marks as touched
+    Command::cargo_bin(PRG)?
+        .args(["-U", "-z", "-n", r"na\nsp", NULL_DATA])
+        .assert()
+        .success()
+        .stdout("2:banana\nsplit\0");
+    Command::cargo_bin(PRG)?
+        .args(["-U", "-z", "-n", r"b\x00c"])
+        .write_stdin("ab\0cd\0ef")
+        .assert()
+        .success()
+        .stdout("1:ab\x002:cd\0");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn nul_ends_line_only_with_null_data() -> TestResult {
+    // Without -z a trailing NUL is part of the line, so `$` can't match before it
+    Command::cargo_bin(PRG)?
+        .args(["-a", "-c", "o$"])
+        .write_stdin("foo\0")
+        .assert()
+        .code(1)
+        .stdout("0\n");
+    Command::cargo_bin(PRG)?
+        .args(["-a", "-x", "foo."])
+        .write_stdin("foo\0")
+        .assert()
+        .success()
+        .stdout("foo\0\n");
+    Command::cargo_bin(PRG)?
+        .args(["-z", "-x", "foo"])
+        .write_stdin("foo\0")
+        .assert()
+        .success()
+        .stdout("foo\0");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn encoding_latin1() -> TestResult {
+    run(
+        &["--encoding", "latin1", "-n", "é", LATIN1],
+        "tests/expected/latin1.txt.e-acute.n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn encoding_utf16le() -> TestResult {
+    run(
+        &["--encoding=utf-16le", "café", UTF16LE],
+        "tests/expected/utf16.txt.cafe",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn encoding_utf16be() -> TestResult {
+    run(
+        &["--encoding=utf-16be", "café", UTF16BE],
+        "tests/expected/utf16.txt.cafe",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn utf16_needs_encoding() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-a", "café", UTF16LE])
+        .assert()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_encoding() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--encoding", "klingon", "café", LATIN1])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("klingon: unknown encoding"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn replace() -> TestResult {
+    run(
+        &["-n", "--replace", "A", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.n.replace",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn replace_capture_groups() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--replace=$2 $1", r"(\w+) (fox)", FOX])
+        .assert()
+        .success()
+        .stdout("The quick fox brown jumps over the lazy dog.\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn replace_perl_named_group() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([
+            "-P",
+            "--replace=[${animal}]",
+            r"(?<animal>\w+)(?= jumps)",
+            FOX,
+        ])
+        .assert()
+        .success()
+        .stdout("The quick brown [fox] jumps over the lazy dog.\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn replace_only_matching() -> TestResult {
+    run(
+        &["-o", "--replace=X", "[Tt]he", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.o.replace",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn replace_count() -> TestResult {
+    run(
+        &["-c", "--replace=A", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn replace_leaves_context_alone() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-A1", "--replace=A", "industries", BUSTLE])
+        .assert()
+        .success()
+        .stdout("Is solemnest of A\r\nEnacted upon earth,\u{2014}\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_replace_multiline() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-U", "--replace=A", "The", BUSTLE])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "--replace can't be combined with --multiline",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+fn temp_copy(src: &str) -> std::io::Result<String> {
+    let dir = env::temp_dir().join(format!("grepr-edit-{}", gen_bad_file()));
+    fs::create_dir(&dir)?;
+    let dst = dir.join(Path::new(src).file_name().unwrap());
+    fs::copy(src, &dst)?;
+    Ok(dst.to_string_lossy().into_owned())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place() -> TestResult {
+    let file = temp_copy(BUSTLE)?;
+    Command::cargo_bin(PRG)?
+        .args(["-I", "--replace=A", "The", &file])
+        .assert()
+        .success()
+        .stdout("");
+    let edited = fs::read_to_string(&file)?;
+    let expected = fs::read_to_string(BUSTLE)?.replace("The", "A");
+    fs::remove_dir_all(Path::new(&file).parent().unwrap())?;
+    assert_eq!(edited, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place_backup() -> TestResult {
+    let file = temp_copy(FOX)?;
+    Command::cargo_bin(PRG)?
+        .args([
+            "--in-place",
+            "--backup=.orig",
+            "--replace=$1",
+            r"(\w+) fox",
+            &file,
+        ])
+        .assert()
+        .success();
+    let edited = fs::read_to_string(&file)?;
+    let backup = fs::read_to_string(format!("{}.orig", file))?;
+    fs::remove_dir_all(Path::new(&file).parent().unwrap())?;
+    assert_eq!(edited, "The quick brown jumps over the lazy dog.\r\n");
+    assert_eq!(backup, fs::read_to_string(FOX)?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place_multiline() -> TestResult {
+    let file = temp_copy(BUSTLE)?;
+    Command::cargo_bin(PRG)?
+        .args(["-UI", "--replace=X", r"house\r\nThe", &file])
+        .assert()
+        .success();
+    let edited = fs::read_to_string(&file)?;
+    fs::remove_dir_all(Path::new(&file).parent().unwrap())?;
+    assert!(edited.starts_with("The bustle in a X morning after death\r\n"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place_no_match() -> TestResult {
+    let file = temp_copy(FOX)?;
+    Command::cargo_bin(PRG)?
+        .args(["-I", "--replace=A", "--backup=.orig", "nobody", &file])
+        .assert()
+        .code(1);
+    let backup_exists = Path::new(&format!("{}.orig", file)).exists();
+    fs::remove_dir_all(Path::new(&file).parent().unwrap())?;
+    assert!(!backup_exists);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place_skips_binary() -> TestResult {
+    let file = temp_copy(BINARY)?;
+    Command::cargo_bin(PRG)?
+        .args(["-I", "--replace=A", "a", &file])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("binary file, use --text"));
+    let unchanged = fs::read(&file)? == fs::read(BINARY)?;
+    fs::remove_dir_all(Path::new(&file).parent().unwrap())?;
+    assert!(unchanged);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place_skips_stdin() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-I", "--replace=A", "The"])
+        .write_stdin("The end\n")
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr(predicate::str::contains("-: can't edit standard input"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place_max_count() -> TestResult {
+    let file = temp_copy(BUSTLE)?;
+    Command::cargo_bin(PRG)?
+        .args(["-I", "-m1", "--replace=A", "The", &file])
+        .assert()
+        .success();
+    let edited = fs::read_to_string(&file)?;
+    let expected = fs::read_to_string(BUSTLE)?.replacen("The", "A", 1);
+    fs::remove_dir_all(Path::new(&file).parent().unwrap())?;
+    assert_eq!(edited, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_in_place_invert() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-I", "-v", "--replace=A", "The", BUSTLE])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+    Command::cargo_bin(PRG)?
+        .args(["-UI", "-m1", "--replace=A", "The", BUSTLE])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "--max-count can't be combined with --in-place --multiline",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_in_place_without_replace() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-I", "The", BUSTLE])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("--replace"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn column() -> TestResult {
+    run(
+        &["-n", "--column", "-i", "the", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.lowercase.insensitive.n.column",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn column_only_matching() -> TestResult {
+    run(
+        &["-o", "--column", "-i", "the", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.lowercase.insensitive.o.column",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn column_counts_bytes() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--column", "-b", "x"])
+        .write_stdin("\u{e9} x\n")
+        .assert()
+        .success()
+        .stdout("4:0:\u{e9} x\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn column_not_on_context() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--column", "-nA1", "industries", BUSTLE])
+        .assert()
+        .success()
+        .stdout(
+            "3:17:Is solemnest of industries\r\n4-Enacted upon earth,\u{2014}\r\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn file_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--file-match", r"house\r\nThe", BUSTLE, EMPTY, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", BUSTLE));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn file_match_anchors_whole_file() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--file-match", r"^The bustle(.|\n)*eternity\.\r\n$"])
+        .args([BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", BUSTLE));
+    Command::cargo_bin(PRG)?
+        .args(["--file-match", "^The morning", BUSTLE])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn file_match_without_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--file-match", "-L", r"house\r\nThe", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn file_match_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--file-match", "-c", "the", BUSTLE, EMPTY])
+        .assert()
+        .success()
+        .stdout(format!("{}:1\n{}:0\n", BUSTLE, EMPTY));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn file_match_quiet() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--file-match", "-q", r"house\r\nThe", BUSTLE])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn zero_width_empty_pattern_color() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "", FOX])
+        .assert()
+        .success()
+        .stdout(fs::read_to_string(FOX)?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn zero_width_star_color() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "a*", FOX])
+        .assert()
+        .success()
+        .stdout(
+            "The quick brown fox jumps over the l\u{1b}[32ma\u{1b}[0mzy dog.\r\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn zero_width_anchor() -> TestResult {
+    run(
+        &["--color=always", "-n", "^", BUSTLE],
+        "tests/expected/bustle.txt.anchor.n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn zero_width_only_matching() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-o", "-e", "a*", "-e", "^", FOX])
+        .assert()
+        .success()
+        .stdout("a\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn zero_width_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "a*", BUSTLE, EMPTY])
+        .assert()
+        .success()
+        .stdout(format!("{}:9\n{}:0\n", BUSTLE, EMPTY));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn progress() -> TestResult {
+    // One file, so the throttle can't skip a redraw on a slow machine
+    let expected = fs::read_to_string(
+        "tests/expected/bustle.txt.the.lowercase.insensitive",
+    )?;
+    let status = "1 files searched, 3 matching lines";
+    Command::cargo_bin(PRG)?
+        .args(["--progress", "-i", "the", BUSTLE])
+        .assert()
+        .success()
+        .stdout(expected)
+        .stderr(format!("\r{}\r{}\r", status, " ".repeat(status.len())));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn progress_clears_before_errors() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["--progress", "fox", FOX, &bad])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::is_match(format!(
+            r"^\r1 files searched, 1 matching lines\r +\r{}: .*\n$",
+            bad
+        ))?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_progress_by_default() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["fox", FOX])
+        .assert()
+        .success()
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn pre() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--pre", UPPER, "FOX", FOX, BUSTLE])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}:THE QUICK BROWN FOX JUMPS OVER THE LAZY DOG.\r\n",
+            FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn pre_failure_continues() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--pre=false", "fox", FOX, BUSTLE])
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr(format!(
+            "{}: false: exit status: 1\n{}: false: exit status: 1\n",
+            FOX, BUSTLE
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn pre_missing_command() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["--pre", &bad, "fox", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::is_match(format!(
+            "^{}: {}: .* [(]os error 2[)]\n$",
+            FOX, bad
+        ))?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn pre_skips_stdin() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--pre=false", "fox"])
+        .write_stdin("fox\n")
+        .assert()
+        .success()
+        .stdout("fox\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_zip() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--search-zip", "-n", "fox", FOX_GZ, FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}:1:The quick brown fox jumps over the lazy dog.\r\n\
+             {}:1:The quick brown fox jumps over the lazy dog.\r\n",
+            FOX_GZ, FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_zip_stdin_magic() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--search-zip", "fox"])
+        .write_stdin(fs::read(FOX_GZ)?)
+        .assert()
+        .success()
+        .stdout("The quick brown fox jumps over the lazy dog.\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_search_zip_by_default() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["fox", FOX_GZ])
+        .assert()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_zip_corrupt() -> TestResult {
+    for (file, error) in [
+        ("tests/zip/corrupt.txt.gz", "invalid gzip header"),
+        ("tests/zip/truncated.txt.gz", "incomplete deflate stream"),
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(["--search-zip", "fox", file, FOX_GZ])
+            .assert()
+            .code(2)
+            .stdout(format!(
+                "{}:The quick brown fox jumps over the lazy dog.\r\n",
+                FOX_GZ
+            ))
+            .stderr(format!("{}: corrupt gzip data: {}\n", file, error));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_archive() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--search-archive", "-n", "fox|nobody", NOTES_TAR])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{0}:fox.txt:1:The fox is in the archive.\r\n\
+             {0}:sub/nobody.txt:1:nobody\r\n",
+            NOTES_TAR
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_archive_files_with_matches() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--search-archive", "-l", "The", NOTES_TAR, FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{0}:fox.txt\n{0}:sub/nobody.txt\n{1}\n",
+            NOTES_TAR, FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_archive_skips_links() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--search-archive", "-c", "fox", NOTES_TAR])
+        .assert()
+        .success()
+        .stdout(format!("{0}:fox.txt:1\n{0}:sub/nobody.txt:0\n", NOTES_TAR));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_archive_twice() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([
+            "--search-archive",
+            "--threads=2",
+            "-c",
+            "fox",
+            NOTES_TAR,
+            FOX,
+            NOTES_TAR,
+        ])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{0}:fox.txt:1\n{0}:sub/nobody.txt:0\n{1}:1\n\
+             {0}:fox.txt:1\n{0}:sub/nobody.txt:0\n",
+            NOTES_TAR, FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_in_place_search_archive() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-I", "--search-archive", "--replace=A", "fox", NOTES_TAR])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_search_archive_by_default() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["fox", NOTES_TAR])
+        .assert()
+        .success()
+        .stdout(format!("Binary file {} matches\n", NOTES_TAR));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_archive_broken() -> TestResult {
+    let broken = "tests/archive/broken.tar";
+    Command::cargo_bin(PRG)?
+        .args(["--search-archive", "nobody", broken, NOTES_TAR])
+        .assert()
+        .code(2)
+        .stdout(format!("{}:sub/nobody.txt:nobody\r\n", NOTES_TAR))
+        .stderr(format!("{}: failed to read entire block\n", broken));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_columns() -> TestResult {
+    run(
+        &["--max-columns=10", "-n", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.n.max-columns10",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn max_columns_keeps_short_lines() -> TestResult {
+    run(
+        &["--max-columns=100", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn max_columns_multibyte() -> TestResult {
+    // The em dash takes bytes 19 to 21, so cutting at 20 drops all of it
+    Command::cargo_bin(PRG)?
+        .args(["--max-columns=20", "earth", BUSTLE])
+        .assert()
+        .success()
+        .stdout("Enacted upon earth, [... 3 more bytes]\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_columns_color() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--max-columns=18", "--color=always", "upon", BUSTLE])
+        .assert()
+        .success()
+        .stdout("Enacted \u{1b}[32mupon\u{1b}[0m earth [... 4 more bytes]\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_columns_color_at_the_cut() -> TestResult {
+    // A match the cut splits keeps its color for what's left of it
+    Command::cargo_bin(PRG)?
+        .args(["--max-columns=12", "--color=always", "quick|brown", FOX])
+        .assert()
+        .success()
+        .stdout(
+            "The \u{1b}[32mquick\u{1b}[0m \u{1b}[32mbr\u{1b}[0m \
+             [... 32 more bytes]\r\n",
+        );
+    // Text that only matches once cut isn't a match
+    Command::cargo_bin(PRG)?
+        .args(["--max-columns=12", "--color=always", "quick|br$", FOX])
+        .assert()
+        .success()
+        .stdout("The \u{1b}[32mquick\u{1b}[0m br [... 32 more bytes]\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_columns_only_matching() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--max-columns=5", "-o", r"\w+ing", BUSTLE])
+        .assert()
+        .success()
+        .stdout("morni [... 2 more bytes]\nsweep [... 3 more bytes]\nputti [... 2 more bytes]\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_max_columns() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--max-columns=wide", "The", BUSTLE])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("wide: invalid max columns"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_invert_no_eol_first() -> TestResult {
+    run(
+        &["-cv", "first", NO_EOL],
+        "tests/expected/no-eol.txt.first.cv",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_invert_no_eol_last() -> TestResult {
+    run(
+        &["-cv", "last", NO_EOL],
+        "tests/expected/no-eol.txt.last.cv",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_invert_no_eol_all() -> TestResult {
+    run(&["-cv", "zzz", NO_EOL], "tests/expected/no-eol.txt.zzz.cv")
+}
+
+// --------------------------------------------------
+#[test]
+fn count_invert_empty() -> TestResult {
+    run(&["-cv", "The", EMPTY], "tests/expected/empty.txt.the.cv")
+}
+
+// --------------------------------------------------
+#[test]
+fn count_invert_multiple() -> TestResult {
+    run(
+        &["-cv", "The", BUSTLE, EMPTY, NO_EOL, FOX],
+        "tests/expected/mixed.the.capitalized.cv",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_invert_no_filename() -> TestResult {
+    run(
+        &["-cvh", "The", BUSTLE, EMPTY],
+        "tests/expected/bustle.empty.the.capitalized.cvh",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_invert_with_filename() -> TestResult {
+    run(
+        &["-cvH", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.cvH",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_invert_stdin_no_eol() -> TestResult {
+    let expected =
+        fs::read_to_string("tests/expected/no-eol.txt.the.cv.stdin")?;
+    Command::cargo_bin(PRG)?
+        .args(["-cv", "The"])
+        .write_stdin(fs::read_to_string(NO_EOL)?)
+        .assert()
+        .code(0)
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn fifo_dir() -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    let dir = env::temp_dir().join(format!("grepr-fifo-{}", gen_bad_file()));
+    fs::create_dir(&dir)?;
+    fs::write(dir.join("file.txt"), "needle in file\n")?;
+    let status = std::process::Command::new("mkfifo")
+        .arg(dir.join("pipe"))
+        .status()?;
+    assert!(status.success());
+    Ok(dir)
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn devices_skipped_when_recursing() -> TestResult {
+    let dir = fifo_dir()?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["-rh", "needle"])
+        .arg(&dir)
+        .timeout(Duration::from_secs(10))
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "needle in file\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn devices_read_when_named() -> TestResult {
+    let dir = fifo_dir()?;
+    let pipe = dir.join("pipe");
+    let writer = {
+        let pipe = pipe.clone();
+        std::thread::spawn(move || fs::write(pipe, "needle in pipe\n"))
+    };
+    let output = Command::cargo_bin(PRG)?
+        .arg("needle")
+        .arg(&pipe)
+        .timeout(Duration::from_secs(10))
+        .output()?;
+    // Unblock the writer ourselves if grepr never opened the pipe
+    if !writer.is_finished() {
+        fs::read(&pipe)?;
+    }
+    let written = writer.join().unwrap();
+    fs::remove_dir_all(&dir)?;
+    written?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "needle in pipe\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn devices_skip_named() -> TestResult {
+    let dir = fifo_dir()?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["--devices=skip", "needle"])
+        .arg(dir.join("pipe"))
+        .timeout(Duration::from_secs(10))
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn devices_read_char_device() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-D", "read", "-c", "needle", "/dev/null"])
+        .assert()
+        .code(1)
+        .stdout("0\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_devices() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--devices=open", "needle", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("isn't a valid value"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn context_field_separator() -> TestResult {
+    run(
+        &["-Hnb", "-C1", "industries", BUSTLE],
+        "tests/expected/bustle.txt.industries.Hnb.C1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn context_field_separator_inverted() -> TestResult {
+    // With -v the selected lines are the ones that don't match
+    Command::cargo_bin(PRG)?
+        .args(["-vn", "-A1", "a", "-"])
+        .write_stdin("x\na\nb\n")
+        .assert()
+        .success()
+        .stdout("1:x\n2-a\n3:b\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn context_multiple_files() -> TestResult {
+    run(
+        &["-A1", "The", BUSTLE, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.A1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn context_multiple_files_adjacent_groups() -> TestResult {
+    run(
+        &[
+            "-C1", "-e", "Is", "-e", "quick", "-e", "Nobody", BUSTLE, FOX,
+            NOBODY,
+        ],
+        "tests/expected/all.is.quick.nobody.C1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn context_multiple_files_no_filename() -> TestResult {
+    run(
+        &["-h", "-A1", "The", BUSTLE, EMPTY, FOX],
+        "tests/expected/all.the.capitalized.A1.h",
+    )
+}
+
+// --------------------------------------------------
+fn count_of(args: &[&str]) -> Result<usize, Box<dyn std::error::Error>> {
+    let output = Command::cargo_bin(PRG)?.args(args).output()?;
+    Ok(String::from_utf8(output.stdout)?.trim_end().parse()?)
+}
+
+// --------------------------------------------------
+#[test]
+fn count_and_invert_count_add_up() -> TestResult {
+    let total = fs::read_to_string(BUSTLE)?.lines().count();
+    for pattern in ["The", "the", "^$", "e", "zzz", ""] {
+        let matching = count_of(&["-c", pattern, BUSTLE])?;
+        let inverted = count_of(&["-cv", pattern, BUSTLE])?;
+        assert_eq!(matching + inverted, total, "pattern {:?}", pattern);
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn invert_count() -> TestResult {
+    run(
+        &["--invert-count", "The", BUSTLE, EMPTY, NO_EOL, FOX],
+        "tests/expected/mixed.the.capitalized.cv",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn invert_count_same_as_count_invert() -> TestResult {
+    for pattern in ["The", "^$", "zzz"] {
+        assert_eq!(
+            count_of(&["--invert-count", pattern, BUSTLE])?,
+            count_of(&["-c", "-v", pattern, BUSTLE])?
+        );
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_number_fox() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "fox", FOX])
+        .assert()
+        .success()
+        .stdout("1:The quick brown fox jumps over the lazy dog.\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_number_invert_fox() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-nv", "fox", FOX])
+        .assert()
+        .code(1)
+        .stdout("");
+    Command::cargo_bin(PRG)?
+        .args(["-nv", "cat", FOX])
+        .assert()
+        .success()
+        .stdout("1:The quick brown fox jumps over the lazy dog.\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_number_invert_counts_skipped_lines() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-nv", "fox", "-", FOX])
+        .write_stdin("fox\nfox\ncat\nfox\ndog\n")
+        .assert()
+        .success()
+        .stdout("(standard input):3:cat\n(standard input):5:dog\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_number_invert_max_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-nv", "-m1", "fox"])
+        .write_stdin("fox\nfox\ncat\ndog\n")
+        .assert()
+        .success()
+        .stdout("3:cat\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_with_filename_single() -> TestResult {
+    run(
+        &["-cH", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.count.H",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_no_filename_single() -> TestResult {
+    run(
+        &["-ch", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.count.h",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_default_single() -> TestResult {
+    run(
+        &["-c", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_with_filename_empty() -> TestResult {
+    run(
+        &["-c", "--with-filename", "The", EMPTY],
+        "tests/expected/empty.txt.the.capitalized.count.H",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_no_filename_multiple() -> TestResult {
+    run(
+        &["-c", "--no-filename", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.count.h",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_filename_last_wins() -> TestResult {
+    run(
+        &["-c", "-h", "-H", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.count.H",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_no_filename_last_wins() -> TestResult {
+    run(
+        &["-c", "-H", "-h", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.count.h",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn path_separator() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-rl", "--sort=path", r"--path-separator=\", "needle"])
+        .arg(format!("{}/src", TREE_DIR))
+        .assert()
+        .success()
+        .stdout(
+            "tests\\tree\\src\\main.txt\ntests\\tree\\src\\nested\\deep.txt\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn path_separator_native() -> TestResult {
+    let sep = std::path::MAIN_SEPARATOR.to_string();
+    Command::cargo_bin(PRG)?
+        .args(["-H", &format!("--path-separator={}", sep), "fox", FOX])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(format!("{}:", FOX)));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn path_separator_leaves_stdin_label() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-H", "--path-separator=|", "--label=a/b", "fox", "-"])
+        .write_stdin("fox\n")
+        .assert()
+        .success()
+        .stdout("a/b:fox\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_path_separator() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--path-separator=//", "fox", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "//: path separator must be a single character",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn trim() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--trim", "-C1", "foo", "-"])
+        .write_stdin("    int x = 1;\n\tfoo(x);\n  \t x\n")
+        .assert()
+        .success()
+        .stdout("int x = 1;\nfoo(x);\nx\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn trim_keeps_offsets_and_columns() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--trim", "-nb", "--column", "x", "-"])
+        .write_stdin("    int x = 1;\n\tfoo(x);\n")
+        .assert()
+        .success()
+        .stdout("1:9:0:int x = 1;\n2:6:15:foo(x);\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn trim_color() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--trim", "--color=always", "foo", "-"])
+        .write_stdin("\t\tfoo()\n")
+        .assert()
+        .success()
+        .stdout("\u{1b}[32mfoo\u{1b}[0m()\n");
+    // Patterns are matched against the line before its indent goes
+    Command::cargo_bin(PRG)?
+        .args(["--trim", "--color=always", r"^\s+f|\)$", "-"])
+        .write_stdin("  foo()\n")
+        .assert()
+        .success()
+        .stdout("\u{1b}[32mf\u{1b}[0moo(\u{1b}[32m)\u{1b}[0m\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_fields() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--null-fields", "-Hn", "fox", FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\x001\0The quick brown fox jumps over the lazy dog.\0",
+            FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_fields_only_matching() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--null-fields", "-Hnbo", "--column", "fox", FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}\x001\x0017\x0016\0fox\0", FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_fields_colons_and_newlines() -> TestResult {
+    // With -U -o a match spans lines, and the NULs still mark every field
+    Command::cargo_bin(PRG)?
+        .args(["--null-fields", "-nHo", "--label=a:b", "-U", r"1:\nx", "-"])
+        .write_stdin("1:\nx\n")
+        .assert()
+        .success()
+        .stdout("a:b\x001\x001:\nx\0");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_fields_names_and_counts() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--null-fields", "-l", "fox", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}\0", FOX));
+    Command::cargo_bin(PRG)?
+        .args(["--null-fields", "-c", "fox", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}\x000\0{}\x001\0", BUSTLE, FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn directories_recurse() -> TestResult {
+    run(
+        &["--directories=recurse", "--sort=path", "needle", TREE_DIR],
+        "tests/expected/needle.tree.sorted",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn directories_skip() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-d", "skip", "fox", INPUTS_DIR, FOX])
+        .assert()
+        .success()
+        .stderr("")
+        // Like a path that failed, the skipped directory doesn't count toward -H
+        .stdout("The quick brown fox jumps over the lazy dog.\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn directories_read() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--directories=read", "fox", INPUTS_DIR])
+        .assert()
+        .code(2)
+        .stderr(format!(
+            "{}: Is a directory (use -r to search recursively)\n",
+            INPUTS_DIR
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn directories_last_wins() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-r", "-d", "skip", "needle", TREE_DIR])
+        .assert()
+        .code(1)
+        .stdout("");
+    run(
+        &["-d", "skip", "-r", "--sort=path", "needle", TREE_DIR],
+        "tests/expected/needle.tree.sorted",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_directories() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--directories=descend", "fox", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("isn't a valid value"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn byte_search() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--binary", r"y\x00d.t", BINARY])
+        .assert()
+        .success()
+        .stdout("10: 79 00 64 61 74\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn byte_search_non_utf8() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--binary", "-H", r"[\x80-\xff]", INVALID])
+        .assert()
+        .success()
+        .stdout(format!("{0}:11: ff\n{0}:12: fe\n", INVALID));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn byte_search_across_lines() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--binary", r"a\nb", "-"])
+        .write_stdin("a\nb")
+        .assert()
+        .success()
+        .stdout("0: 61 0a 62\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn byte_search_count_and_max_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--binary", "-c", r"\x00", BINARY])
+        .assert()
+        .success()
+        .stdout("2\n");
+    Command::cargo_bin(PRG)?
+        .args(["--binary", "-m1", r"\x00", BINARY])
+        .assert()
+        .success()
+        .stdout("4: 00\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_byte_search_invert() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--binary", "-v", "x", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn field_separator_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "--count-total", "--field-separator= => ", "The"])
+        .args([BUSTLE, EMPTY, FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{} => 3\n{} => 0\n{} => 1\ntotal => 4\n",
+            BUSTLE, EMPTY, FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn field_separator_lines() -> TestResult {
+    // Context lines keep their `-`; standard input keeps its label
+    Command::cargo_bin(PRG)?
+        .args(["-Hnb", "-A1", "--field-separator=\t", "b", "-"])
+        .write_stdin("a:b\nc\n")
+        .assert()
+        .success()
+        .stdout("(standard input)\t1\t0\ta:b\n(standard input)-2-4-c\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn field_separator_with_null() -> TestResult {
+    // The NUL after a file name stays, and --null-fields wins outright
+    Command::cargo_bin(PRG)?
+        .args(["-Zn", "--field-separator=|", "fox", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\x001|The quick brown fox jumps over the lazy dog.\r\n",
+            FOX
+        ));
+    Command::cargo_bin(PRG)?
+        .args(["--null-fields", "-c", "--field-separator=|", "fox", FOX])
+        .assert()
+        .success()
+        .stdout("1\0");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn first_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-on", "--first-match", "[a-z]+", "-"])
+        .write_stdin("one two\nthree\n")
+        .assert()
+        .success()
+        .stdout("1:one\n2:three\n");
+    // All matches are grep's default
+    Command::cargo_bin(PRG)?
+        .args(["-on", "[a-z]+", "-"])
+        .write_stdin("one two\nthree\n")
+        .assert()
+        .success()
+        .stdout("1:one\n1:two\n2:three\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn first_match_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-oci", "--first-match", "the", FOX])
+        .assert()
+        .success()
+        .stdout("1\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn first_match_multiline() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-Uo", "--first-match", r"b\nc|a", "-"])
+        .write_stdin("a b\nc a\n")
+        .assert()
+        .success()
+        // Matches belong to the line they start on, so `b\nc` is line 1's second
+        .stdout("a\na\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_first_match_without_only_matching() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--first-match", "fox", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--only-matching"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn regex_size_limit() -> TestResult {
+    // Raising the limit, or keeping it above what the pattern needs, changes nothing
+    for limit in ["10M", "1G"] {
+        Command::cargo_bin(PRG)?
+            .args([&format!("--regex-size-limit={}", limit), r"\w{5}", FOX])
+            .assert()
+            .success()
+            .stdout("The quick brown fox jumps over the lazy dog.\r\n");
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_regex_size_limit() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--regex-size-limit=1K", r"\w{50}", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "\"\\w{50}\": compiled regex is over the size limit of 1024 bytes",
+        ));
+    // Patterns can fit one by one and still be too big together
+    let mut args = vec!["--regex-size-limit=2K".to_string()];
+    for class in ["[ab]", "[cd]", "[ef]", "[gh]"] {
+        args.extend(["-e".to_string(), format!("{}{{20}}", class)]);
+    }
+    Command::cargo_bin(PRG)?
+        .args(&args)
+        .arg(FOX)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "All patterns: compiled regex is over the size limit of 2048 bytes",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dfa_size_limit() -> TestResult {
+    // A small cache only slows matching down
+    Command::cargo_bin(PRG)?
+        .args(["--dfa-size-limit=1K", "-c", "[a-z]+ fox", FOX])
+        .assert()
+        .success()
+        .stdout("1\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_size_limits() -> TestResult {
+    for (arg, error) in [
+        ("--regex-size-limit=lots", "lots: invalid regex size limit"),
+        ("--dfa-size-limit=1x", "1x: invalid DFA size limit"),
+    ] {
+        Command::cargo_bin(PRG)?
+            .args([arg, "fox", FOX])
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains(error));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn timeout_not_reached() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--timeout=60", "fox", FOX])
+        .assert()
+        .success()
+        .stdout("The quick brown fox jumps over the lazy dog.\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn timeout_zero() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--timeout=0", "fox", FOX])
+        .assert()
+        .code(3)
+        .stdout("")
+        .stderr("search timed out after 0s\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn timeout_prints_found_so_far() -> TestResult {
+    // The slow file finishes past the deadline, so the last one is never searched
+    Command::cargo_bin(PRG)?
+        .args(["--threads=1", "--timeout=0.3", "--pre", SLOW, "-l", "e"])
+        .args([FOX, NOBODY, BUSTLE])
+        .assert()
+        .code(3)
+        .stdout(format!("{}\n{}\n", FOX, NOBODY))
+        .stderr("search timed out after 0.3s\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_timeout() -> TestResult {
+    for secs in ["soon", "-1", "inf"] {
+        Command::cargo_bin(PRG)?
+            .args([&format!("--timeout={}", secs), "fox", FOX])
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains(format!(
+                "{}: invalid timeout",
+                secs
+            )));
+    }
+    for mode in ["-U", "--file-match", "--binary"] {
+        Command::cargo_bin(PRG)?
+            .args(["--timeout=1", mode, "fox", FOX])
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains("cannot be used with"));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn and_patterns() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-i", "the", "--and=up", "--and", "heart", BUSTLE])
+        .assert()
+        .success()
+        .stdout("The sweeping up the heart,\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn not_patterns() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["The", "--not=house", "--not", "heart", BUSTLE])
+        .assert()
+        .success()
+        .stdout("The morning after death\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn and_not_patterns_inverted() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-v", "-c", "The", "--not=death", BUSTLE])
+        .assert()
+        .success()
+        .stdout("7\n");
+    Command::cargo_bin(PRG)?
+        .args(["fox", "--and=cat", FOX])
+        .assert()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn and_not_patterns_files() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-l", "fox", "--and=dog", FOX, BUSTLE])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", FOX));
+    Command::cargo_bin(PRG)?
+        .args(["-c", "The", "--not=house", BUSTLE])
+        .assert()
+        .success()
+        .stdout("2\n");
+    Command::cargo_bin(PRG)?
+        .args(["--file-match", "-l", "The", "--and=eternity", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", BUSTLE));
+    Command::cargo_bin(PRG)?
+        .args(["--file-match", "-c", "The", "--not=fox", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}:1\n{}:0\n", BUSTLE, FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn and_not_patterns_in_place() -> TestResult {
+    let file = temp_copy(BUSTLE)?;
+    Command::cargo_bin(PRG)?
+        .args(["-I", "--replace=A", "The", "--not=house", &file])
+        .assert()
+        .success();
+    let edited = fs::read_to_string(&file)?;
+    let expected = fs::read_to_string(BUSTLE)?
+        .replace("The morning", "A morning")
+        .replace("The sweeping", "A sweeping");
+    fs::remove_dir_all(Path::new(&file).parent().unwrap())?;
+    assert_eq!(edited, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn and_patterns_color() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "quick", "--and=lazy", "--not=cat", FOX])
+        .assert()
+        .success()
+        .stdout(
+            "The \u{1b}[32mquick\u{1b}[0m brown fox jumps over the \
+             \u{1b}[32mlazy\u{1b}[0m dog.\r\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_and_pattern_multiline() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-U", "fox", "--and=dog", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Command::cargo_bin(PRG)?
+        .args(["fox", "--not=(dog", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid pattern \"(dog\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn warns_dir_not_recursive() -> TestResult {
+    let stdout = "The quick brown fox jumps over the lazy dog.";
+    Command::cargo_bin(PRG)?
+        .args(["fox", INPUTS_DIR, FOX])
+        .assert()
+        .stderr(format!(
+            "{}: Is a directory (use -r to search recursively)\n",
+            INPUTS_DIR
+        ))
+        .stdout(predicate::str::contains(stdout));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dir_not_recursive_no_prefix() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["fox", FOX, INPUTS_DIR])
+        .assert()
+        .code(2)
+        .stdout("The quick brown fox jumps over the lazy dog.\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn bad_file_no_prefix() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["fox", &bad, FOX])
+        .assert()
+        .code(2)
+        .stdout("The quick brown fox jumps over the lazy dog.\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_messages_dir_not_recursive() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-s", "fox", INPUTS_DIR, FOX])
+        .assert()
+        .code(2)
+        .stderr("")
+        .stdout("The quick brown fox jumps over the lazy dog.\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_messages_bad_file() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["--no-messages", "foo", &bad])
+        .assert()
+        .code(2)
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_messages_keeps_fatal_errors() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-s", "*foo", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid pattern \"*foo\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_stdin_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "foo"])
+        .write_stdin("")
+        .assert()
+        .code(1)
+        .stdout("0\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_stdin_count_invert() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-cv", "foo"])
+        .write_stdin("")
+        .assert()
+        .code(1)
+        .stdout("0\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_stdin_count_total() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--count-total", "foo"])
+        .write_stdin("")
+        .assert()
+        .code(1)
+        .stdout("0\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_stdin_files_with_matches() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-l", "foo"])
+        .write_stdin("")
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_stdin_files_without_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-L", "foo"])
+        .write_stdin("")
+        .assert()
+        .code(0)
+        .stdout("(standard input)\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_stdin_quiet() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-q", "foo"])
+        .write_stdin("")
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_stdin_invert() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-v", "foo"])
+        .write_stdin("")
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_stdin_line_number() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "foo"])
+        .write_stdin("")
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_stdin_only_matching() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-o", "foo"])
+        .write_stdin("")
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_stdin_json() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--json", "foo"])
+        .write_stdin("")
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stdin_label_prefix() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "fox", "-", FOX])
+        .write_stdin("a fox\n")
+        .assert()
+        .success()
+        .stdout(format!(
+            "(standard input):1:a fox\n\
+             {}:1:The quick brown fox jumps over the lazy dog.\r\n",
+            FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stdin_label_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-cH", "fox"])
+        .write_stdin("a fox\n")
+        .assert()
+        .success()
+        .stdout("(standard input):1\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stdin_label_files_with_matches() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-l", "fox", FOX, "-"])
+        .write_stdin("a fox\n")
+        .assert()
+        .success()
+        .stdout(format!("{}\n(standard input)\n", FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn label() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--label=logs", "-H", "fox"])
+        .write_stdin("a fox\n")
+        .assert()
+        .success()
+        .stdout("logs:a fox\n");
+    Command::cargo_bin(PRG)?
+        .args(["--label", "logs", "-L", "cat", "-", FOX])
+        .write_stdin("a fox\n")
+        .assert()
+        .success()
+        .stdout(format!("logs\n{}\n", FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn label_only_names_stdin() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--label=logs", "-H", "fox", FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}:The quick brown fox jumps over the lazy dog.\r\n",
+            FOX
+        ));
     Ok(())
 }
 
@@ -271,7 +4503,7 @@ fn stdin_insensitive_count() -> TestResult {
     let expected = fs::read_to_string(expected_file)?;
 
     Command::cargo_bin(PRG)?
-        .args(&["-ci", "the", "-"])
+        .args(["-ci", "the", "-"])
         .write_stdin(input)
         .assert()
         .stdout(expected);
@@ -0,0 +1,3 @@
+tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.
+tests/inputs/nobody.txt:I'm Nobody! Who are you?
+tests/inputs/nobody.txt:Are you—Nobody—too?
@@ -0,0 +1,7 @@
+The bustle in a house
+The morning after death
+Is solemnest of industries
+--
+The sweeping up the heart,
+And putting love away
+The quick brown fox jumps over the lazy dog.
@@ -0,0 +1,4 @@
+3
+0
+1
+1
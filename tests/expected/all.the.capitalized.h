@@ -0,0 +1,5 @@
+The bustle in a house
+The morning after death
+The sweeping up the heart,
+The quick brown fox jumps over the lazy dog.
+Then there's a pair of us!
@@ -0,0 +1,3 @@
+0:The bustle in a house
+23:The morning after death
+102:The sweeping up the heart,
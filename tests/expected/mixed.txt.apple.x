@@ -0,0 +1 @@
+apple
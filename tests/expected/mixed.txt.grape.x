@@ -0,0 +1 @@
+grape
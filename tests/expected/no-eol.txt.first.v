@@ -0,0 +1 @@
+last line